@@ -0,0 +1,659 @@
+use crate::arch_def::encoding::{BitField, Encoding, FieldValue, operand_index, sign_extend};
+use crate::arch_def::{
+    Architecture, CaseSensitivity, Endianness, Instruction, OperandKind, Symbol,
+};
+use crate::assembler::AssemblerPass;
+use crate::assembler::diagnostics::Location;
+use crate::assembler::expression::Expression;
+use crate::assembler::passes::parse::PlausibleOperator;
+use crate::assembler::passes::parse_operands::Operands;
+use crate::assembler::passes::tokenize::{Token, TokenizePass, parse_value};
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+/// An architecture loaded at runtime from a description file.
+///
+/// ```text
+/// # Comments start with a hash
+/// word 16
+/// endianness little
+/// data_word 16
+/// address_unit 8
+/// case insensitive-mnemonics
+/// symbol Reg "r" 0..8
+/// alias sp r7
+/// operand_kind Reg symbol Reg
+/// operand_kind Imm6s signed 6
+/// operand_kind Imm8u unsigned 8
+/// instruction add (Reg rd, Reg ra, Reg rb) => opcode[15:12] = 0, rd[11:9], ra[8:6], rb[2:0]
+/// instruction in (Reg rd, Imm8u port) => opcode[15:12] = 7, rd[11:9], port[7:0]
+/// ```
+///
+/// The description follows [`define_architecture!`](crate::define_architecture):
+/// symbols are a prefix followed by a number in the given range, operand kinds
+/// take a symbol or a value that fits in the given number of bits, and
+/// instructions are encoded as bit fields in a word of `word` bits. Ranges may
+/// be inclusive (`0..=7`), and `case` takes `sensitive`, `insensitive-mnemonics`
+/// or `insensitive`, like [`CaseSensitivity`].
+///
+/// `alias` gives another name to a symbol, see [`Architecture::symbol_aliases`].
+/// `extension` declares an extension, which instructions belong to when they
+/// end with `in extension`. Instructions may then end with `if condition`, an
+/// expression of their operands by name like `rd != ra`, which must hold for
+/// them to be accepted, see [`Instruction::validate`].
+/// `endianness` (`little` or `big`), the width in bits of `data_word` and of
+/// `address_unit` are the properties of [`Architecture`] of the same name, and
+/// are optional: they default to little endian, 16-bit words and bytes.
+///
+/// The architecture's functions don't take a `self`, so the description is
+/// global: it's loaded once with [`DynamicArchitecture::load`], before assembling.
+#[derive(Clone, Debug)]
+pub enum DynamicArchitecture {}
+
+static DESCRIPTION: OnceLock<Description> = OnceLock::new();
+
+#[derive(Debug)]
+struct Description {
+    case_sensitivity: CaseSensitivity,
+    endianness: Endianness,
+    /// The sizes in bytes of data words, instruction words and addressable units
+    word_size: usize,
+    instruction_word_size: usize,
+    address_unit: usize,
+    symbols: Vec<SymbolDefinition>,
+    aliases: Vec<(&'static str, &'static str)>,
+    extensions: Vec<&'static str>,
+    operand_kinds: Vec<OperandKindDefinition>,
+    instructions: &'static [DynamicInstruction],
+}
+
+#[derive(Debug)]
+struct SymbolDefinition {
+    name: String,
+    prefix: String,
+    /// The range of numbers, end excluded
+    numbers: (u8, u16),
+}
+
+#[derive(Debug)]
+struct OperandKindDefinition {
+    name: String,
+    class: OperandClass,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum OperandClass {
+    /// A symbol, given by its index
+    Symbol(usize),
+    Signed(u32),
+    Unsigned(u32),
+}
+
+#[derive(Debug)]
+struct InstructionDefinition {
+    name: String,
+    operands: Vec<DynamicOperandKind>,
+    encoding: Encoding,
+    extension: Option<&'static str>,
+    constraint: Option<Constraint>,
+}
+
+/// A condition on the operands of an instruction, which refers to them by name.
+#[derive(Debug)]
+struct Constraint {
+    operands: Vec<String>,
+    condition: Expression,
+    source: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DynamicInstruction(&'static InstructionDefinition);
+
+/// An operand kind, given by its index in the description.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DynamicOperandKind(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DynamicSymbol {
+    /// The index of the symbol in the description
+    symbol: usize,
+    number: u8,
+}
+
+impl DynamicArchitecture {
+    /// Loads the description at `path`. Can only be called once.
+    pub fn load(path: &Path) -> Result<(), Box<dyn Error>> {
+        let source = fs::read_to_string(path)
+            .map_err(|error| format!("Can't read {}: {error}", path.display()))?;
+        let description = parse_description(path.to_string_lossy().into(), &source)?;
+        DESCRIPTION
+            .set(description)
+            .map_err(|_| "An architecture description was already loaded".into())
+    }
+
+    fn description() -> &'static Description {
+        DESCRIPTION
+            .get()
+            .expect("The architecture description isn't loaded")
+    }
+}
+
+impl Architecture for DynamicArchitecture {
+    type Instruction = DynamicInstruction;
+    type OperandKind = DynamicOperandKind;
+    type Symbol = DynamicSymbol;
+
+    fn case_sensitivity() -> CaseSensitivity {
+        Self::description().case_sensitivity
+    }
+
+    fn extensions() -> &'static [&'static str] {
+        &Self::description().extensions
+    }
+
+    fn symbol_aliases() -> &'static [(&'static str, &'static str)] {
+        &Self::description().aliases
+    }
+
+    fn endianness() -> Endianness {
+        Self::description().endianness
+    }
+
+    fn word_size() -> usize {
+        Self::description().word_size
+    }
+
+    fn instruction_word_size() -> usize {
+        Self::description().instruction_word_size
+    }
+
+    fn address_unit() -> usize {
+        Self::description().address_unit
+    }
+}
+
+impl Instruction<DynamicArchitecture> for DynamicInstruction {
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    fn operands(&self) -> impl IntoIterator<Item = DynamicOperandKind> {
+        self.0.operands.iter().copied()
+    }
+
+    fn emit(&self, operands: impl IntoIterator<Item = isize>) -> impl IntoIterator<Item = u8> {
+        let encoding = &self.0.encoding;
+        let word = encoding
+            .encode(&Vec::from_iter(operands))
+            .unwrap_or_else(|message| panic!("Can't encode {}: {message}", self.name()));
+        DynamicArchitecture::endianness()
+            .bytes(word, encoding.width as usize / 8)
+            .unwrap_or_else(|message| panic!("Can't encode {}: {message}", self.name()))
+    }
+
+    fn enumerate() -> impl IntoIterator<Item = &'static Self> {
+        DynamicArchitecture::description().instructions
+    }
+
+    fn extension(&self) -> Option<&'static str> {
+        self.0.extension
+    }
+
+    fn validate(&self, operands: &[isize]) -> Result<(), Box<dyn Error>> {
+        let Some(constraint) = &self.0.constraint else {
+            return Ok(());
+        };
+        let value = constraint.condition.evaluate(&|name| {
+            let index = constraint
+                .operands
+                .iter()
+                .position(|operand| operand == name)?;
+            operands.get(index).copied()
+        })?;
+        if value == 0 {
+            return Err(format!("Expected {}", constraint.source).into());
+        }
+        Ok(())
+    }
+
+    fn size(&self, _operands: &[PlausibleOperator<DynamicArchitecture>]) -> Option<usize> {
+        Some(self.0.encoding.width as usize / 8)
+    }
+
+    fn decode(bytes: &[u8]) -> Option<(Self, Operands<DynamicArchitecture>)> {
+        DynamicArchitecture::description()
+            .instructions
+            .iter()
+            .find_map(|instruction| {
+                let encoding = &instruction.0.encoding;
+                let word = DynamicArchitecture::endianness()
+                    .value(bytes.get(..encoding.width as usize / 8)?);
+                let fields = encoding.decode(word, instruction.0.operands.len())?;
+                let operands = instruction
+                    .0
+                    .operands
+                    .iter()
+                    .zip(fields)
+                    .map(|(kind, (value, width))| match kind.class() {
+                        OperandClass::Signed(_) => sign_extend(value, width),
+                        _ => value as isize,
+                    })
+                    .collect();
+                Some((*instruction, operands))
+            })
+    }
+}
+
+impl DynamicOperandKind {
+    fn class(&self) -> OperandClass {
+        DynamicArchitecture::description().operand_kinds[self.0].class
+    }
+}
+
+impl OperandKind<DynamicArchitecture> for DynamicOperandKind {
+    type Operand = isize;
+
+    fn parse(
+        &self,
+        plausible_operator: PlausibleOperator<DynamicArchitecture>,
+    ) -> Result<isize, Box<dyn Error>> {
+        match (self.class(), plausible_operator) {
+            (OperandClass::Symbol(symbol), PlausibleOperator::Symbol(parsed))
+                if parsed.symbol == symbol =>
+            {
+                Ok(parsed.number as isize)
+            }
+            (OperandClass::Signed(bits), PlausibleOperator::Value(value)) => {
+                if (-(1 << (bits - 1))..1 << (bits - 1)).contains(&value) {
+                    Ok(value)
+                } else {
+                    Err(format!("{value} doesn't fit in a signed {bits}-bit immediate").into())
+                }
+            }
+            (OperandClass::Unsigned(bits), PlausibleOperator::Value(value)) => {
+                if (0..1 << bits).contains(&value) {
+                    Ok(value)
+                } else {
+                    Err(format!("{value} doesn't fit in an unsigned {bits}-bit immediate").into())
+                }
+            }
+            (OperandClass::Symbol(symbol), _) => Err(format!(
+                "Expected a {} symbol",
+                DynamicArchitecture::description().symbols[symbol].name
+            )
+            .into()),
+            _ => Err("Expected a value".into()),
+        }
+    }
+
+    fn format(&self, operand: &isize) -> String {
+        match self.class() {
+            OperandClass::Symbol(symbol) => DynamicSymbol {
+                symbol,
+                number: *operand as u8,
+            }
+            .to_string(),
+            _ => operand.to_string(),
+        }
+    }
+}
+
+impl Symbol<DynamicArchitecture> for DynamicSymbol {
+    fn parse(symbol: &str) -> Result<Self, Box<dyn Error>> {
+        DynamicArchitecture::description()
+            .symbols
+            .iter()
+            .enumerate()
+            .find_map(|(index, definition)| {
+                let number = symbol
+                    .strip_prefix(&definition.prefix)?
+                    .parse::<u8>()
+                    .ok()?;
+                let (start, end) = definition.numbers;
+                (start <= number && u16::from(number) < end).then_some(Self {
+                    symbol: index,
+                    number,
+                })
+            })
+            .ok_or_else(|| format!("Invalid symbol: {symbol}").into())
+    }
+
+    fn enumerate() -> impl IntoIterator<Item = Self> {
+        let symbols = &DynamicArchitecture::description().symbols;
+        symbols
+            .iter()
+            .enumerate()
+            .flat_map(|(index, definition)| {
+                let (start, end) = definition.numbers;
+                (u16::from(start)..end).map(move |number| Self {
+                    symbol: index,
+                    number: number as u8,
+                })
+            })
+            .collect::<Vec<_>>()
+    }
+}
+
+impl Display for DynamicSymbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let definition = &DynamicArchitecture::description().symbols[self.symbol];
+        write!(f, "{}{}", definition.prefix, self.number)
+    }
+}
+
+fn parse_description(file: Rc<str>, source: &str) -> Result<Description, Box<dyn Error>> {
+    let mut description = Description {
+        case_sensitivity: CaseSensitivity::default(),
+        endianness: Endianness::default(),
+        word_size: 2,
+        instruction_word_size: 1,
+        address_unit: 1,
+        symbols: vec![],
+        aliases: vec![],
+        extensions: vec![],
+        operand_kinds: vec![],
+        instructions: &[],
+    };
+    let mut width = None;
+    let mut instructions = vec![];
+
+    for (number, line) in source.lines().enumerate() {
+        let location = Location::new(file.clone(), number + 1);
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let result = match keyword {
+            "word" => parse_width(rest).map(|parsed| {
+                width = Some(parsed);
+                description.instruction_word_size = parsed as usize / 8;
+            }),
+            "endianness" => parse_endianness(rest).map(|parsed| description.endianness = parsed),
+            "data_word" => {
+                parse_width(rest).map(|parsed| description.word_size = parsed as usize / 8)
+            }
+            "address_unit" => {
+                parse_width(rest).map(|parsed| description.address_unit = parsed as usize / 8)
+            }
+            "case" => {
+                parse_case_sensitivity(rest).map(|parsed| description.case_sensitivity = parsed)
+            }
+            "symbol" => parse_symbol(rest).map(|symbol| description.symbols.push(symbol)),
+            "alias" => parse_alias(rest).map(|alias| description.aliases.push(alias)),
+            "extension" => {
+                parse_extension(rest).map(|extension| description.extensions.push(extension))
+            }
+            "operand_kind" => parse_operand_kind(&description, rest)
+                .map(|kind| description.operand_kinds.push(kind)),
+            "instruction" => width
+                .ok_or_else(|| "The word width must be given before the instructions".into())
+                .and_then(|width| parse_instruction(&description, width, rest))
+                .map(|instruction| instructions.push(instruction)),
+            _ => Err(format!("Unknown keyword: {keyword}").into()),
+        };
+        result.map_err(|message| format!("{location}: {message}"))?;
+    }
+
+    if instructions.is_empty() {
+        return Err(format!("{file}: No instructions are defined").into());
+    }
+
+    description.instructions = Vec::leak(
+        instructions
+            .into_iter()
+            .map(|instruction| DynamicInstruction(Box::leak(Box::new(instruction))))
+            .collect(),
+    );
+    Ok(description)
+}
+
+fn parse_width(width: &str) -> Result<u32, Box<dyn Error>> {
+    match width.parse() {
+        Ok(width @ (8 | 16 | 24 | 32 | 40 | 48 | 56 | 64)) => Ok(width),
+        _ => Err(format!("Invalid width: {width}").into()),
+    }
+}
+
+fn parse_endianness(endianness: &str) -> Result<Endianness, Box<dyn Error>> {
+    match endianness {
+        "little" => Ok(Endianness::Little),
+        "big" => Ok(Endianness::Big),
+        _ => Err(format!("Invalid endianness: {endianness}").into()),
+    }
+}
+
+fn parse_case_sensitivity(case_sensitivity: &str) -> Result<CaseSensitivity, Box<dyn Error>> {
+    match case_sensitivity {
+        "sensitive" => Ok(CaseSensitivity::Sensitive),
+        "insensitive-mnemonics" => Ok(CaseSensitivity::InsensitiveMnemonics),
+        "insensitive" => Ok(CaseSensitivity::Insensitive),
+        _ => Err(format!("Invalid case sensitivity: {case_sensitivity}").into()),
+    }
+}
+
+/// Parses `Name "prefix" start..end`.
+fn parse_symbol(symbol: &str) -> Result<SymbolDefinition, Box<dyn Error>> {
+    let [name, prefix, range] = symbol.split_whitespace().collect::<Vec<_>>()[..] else {
+        return Err("Expected a symbol name, prefix and range".into());
+    };
+    let prefix = prefix
+        .strip_prefix('"')
+        .and_then(|prefix| prefix.strip_suffix('"'))
+        .ok_or("Expected a quoted prefix")?;
+
+    let (start, end, inclusive) = match range.split_once("..=") {
+        Some((start, end)) => (start, end, true),
+        None => {
+            let (start, end) = range.split_once("..").ok_or("Expected a range")?;
+            (start, end, false)
+        }
+    };
+    let start = u8::try_from(parse_number(start)?)?;
+    let end = u16::try_from(parse_number(end)?)? + u16::from(inclusive);
+    if end > 256 || u16::from(start) >= end {
+        return Err(format!("Invalid range: {range}").into());
+    }
+
+    Ok(SymbolDefinition {
+        name: name.to_string(),
+        prefix: prefix.to_string(),
+        numbers: (start, end),
+    })
+}
+
+/// Parses `alias symbol`.
+fn parse_alias(alias: &str) -> Result<(&'static str, &'static str), Box<dyn Error>> {
+    let [alias, symbol] = alias.split_whitespace().collect::<Vec<_>>()[..] else {
+        return Err("Expected an alias and a symbol".into());
+    };
+    Ok((
+        String::leak(alias.to_string()),
+        String::leak(symbol.to_string()),
+    ))
+}
+
+fn parse_extension(extension: &str) -> Result<&'static str, Box<dyn Error>> {
+    match extension.split_whitespace().collect::<Vec<_>>()[..] {
+        [extension] => Ok(String::leak(extension.to_string())),
+        _ => Err("Expected an extension name".into()),
+    }
+}
+
+/// Parses `Name symbol Symbol`, `Name signed bits` or `Name unsigned bits`.
+fn parse_operand_kind(
+    description: &Description,
+    kind: &str,
+) -> Result<OperandKindDefinition, Box<dyn Error>> {
+    let [name, class, argument] = kind.split_whitespace().collect::<Vec<_>>()[..] else {
+        return Err("Expected an operand kind name, class and argument".into());
+    };
+
+    let class = match class {
+        "symbol" => OperandClass::Symbol(
+            description
+                .symbols
+                .iter()
+                .position(|symbol| symbol.name == argument)
+                .ok_or_else(|| format!("Unknown symbol: {argument}"))?,
+        ),
+        "signed" | "unsigned" => {
+            let bits = u32::try_from(parse_number(argument)?)?;
+            if !(1..64).contains(&bits) {
+                return Err(format!("Invalid number of bits: {bits}").into());
+            }
+            match class {
+                "signed" => OperandClass::Signed(bits),
+                _ => OperandClass::Unsigned(bits),
+            }
+        }
+        _ => return Err(format!("Unknown operand class: {class}").into()),
+    };
+
+    Ok(OperandKindDefinition {
+        name: name.to_string(),
+        class,
+    })
+}
+
+/// Parses `name (Kind operand, ...) => field[high:low], field[high:low] = value, ...`,
+/// optionally followed by `in extension`, then by `if condition`.
+fn parse_instruction(
+    description: &Description,
+    width: u32,
+    instruction: &str,
+) -> Result<InstructionDefinition, Box<dyn Error>> {
+    let (signature, fields) = instruction
+        .split_once("=>")
+        .ok_or("Expected => before the encoding")?;
+    let (name, operands) = signature
+        .trim()
+        .strip_suffix(')')
+        .and_then(|signature| signature.split_once('('))
+        .ok_or("Expected the operands in parentheses")?;
+    let (fields, condition) = match fields.rsplit_once(" if ") {
+        Some((fields, condition)) => (fields, Some(condition.trim())),
+        None => (fields, None),
+    };
+    let (fields, extension) = match fields.rsplit_once(" in ") {
+        Some((fields, extension)) => {
+            let extension = extension.trim();
+            let extension = description
+                .extensions
+                .iter()
+                .find(|declared| **declared == extension)
+                .ok_or_else(|| format!("Unknown extension: {extension}"))?;
+            (fields, Some(*extension))
+        }
+        None => (fields, None),
+    };
+
+    let mut kinds = vec![];
+    let mut names = vec![];
+    for operand in operands
+        .split(',')
+        .map(str::trim)
+        .filter(|operand| !operand.is_empty())
+    {
+        let [kind, name] = operand.split_whitespace().collect::<Vec<_>>()[..] else {
+            return Err(format!("Expected an operand kind and name: {operand}").into());
+        };
+        kinds.push(DynamicOperandKind(
+            description
+                .operand_kinds
+                .iter()
+                .position(|definition| definition.name == kind)
+                .ok_or_else(|| format!("Unknown operand kind: {kind}"))?,
+        ));
+        names.push(name);
+    }
+
+    let fields = fields
+        .split(',')
+        .map(|field| parse_field(field.trim(), &names))
+        .collect::<Result<Vec<_>, _>>()?;
+    let encoding = Encoding {
+        width,
+        fields: Vec::leak(fields),
+    };
+    encoding.check(kinds.len())?;
+    let constraint = condition
+        .map(|condition| parse_constraint(condition, &names))
+        .transpose()?;
+
+    Ok(InstructionDefinition {
+        name: name.trim().to_string(),
+        operands: kinds,
+        encoding,
+        extension,
+        constraint,
+    })
+}
+
+/// Parses a condition on the `operands`, written like the expressions of the assembler.
+fn parse_constraint(condition: &str, operands: &[&str]) -> Result<Constraint, Box<dyn Error>> {
+    let tokens = TokenizePass::default()
+        .apply_all(condition.chars())
+        .into_iter()
+        .filter(|token| !matches!(token, Token::Location(_) | Token::LineFeed))
+        .collect::<Vec<_>>();
+    let condition_expression = Expression::parse(&tokens)?;
+
+    let unknown = RefCell::new(None);
+    condition_expression.map_identifiers(&|name| {
+        if !operands.contains(&name) {
+            unknown.borrow_mut().get_or_insert_with(|| name.to_string());
+        }
+        Expression::Identifier(name.to_string())
+    });
+    if let Some(name) = unknown.into_inner() {
+        return Err(format!("Unknown operand in condition: {name}").into());
+    }
+
+    Ok(Constraint {
+        operands: operands.iter().map(|operand| operand.to_string()).collect(),
+        condition: condition_expression,
+        source: condition.to_string(),
+    })
+}
+
+/// Parses `name[high:low]`, or `name[high:low] = value` for fixed bits.
+fn parse_field(field: &str, operands: &[&str]) -> Result<BitField, Box<dyn Error>> {
+    let (field, fixed) = match field.split_once('=') {
+        Some((field, fixed)) => (field.trim(), Some(parse_number(fixed.trim())?)),
+        None => (field, None),
+    };
+    let (name, bits) = field
+        .strip_suffix(']')
+        .and_then(|field| field.split_once('['))
+        .ok_or_else(|| format!("Expected a field like name[high:low]: {field}"))?;
+    let (high, low) = bits
+        .split_once(':')
+        .ok_or_else(|| format!("Expected the bits of the field like [high:low]: {field}"))?;
+
+    let value = match fixed {
+        Some(value) => FieldValue::Fixed(value),
+        None if operands.contains(&name) => FieldValue::Operand(operand_index(name, operands)),
+        None => return Err(format!("Unknown operand: {name}").into()),
+    };
+
+    Ok(BitField {
+        name: String::leak(name.to_string()),
+        high: u32::try_from(parse_number(high)?)?,
+        low: u32::try_from(parse_number(low)?)?,
+        value,
+    })
+}
+
+fn parse_number(number: &str) -> Result<u64, Box<dyn Error>> {
+    parse_value(number)
+        .and_then(|number| u64::try_from(number).ok())
+        .ok_or_else(|| format!("Invalid number: {number}").into())
+}
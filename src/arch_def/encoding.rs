@@ -0,0 +1,198 @@
+use std::error::Error;
+
+/// The format of an instruction word, as a list of bit fields.
+///
+/// ```text
+/// opcode[15:12] = 2, rd[11:9], ra[8:6], imm[5:0]
+/// ```
+///
+/// Fields either hold one of the operands of the instruction, or fixed bits
+/// that identify it. Bits that aren't part of any field are zero, and words
+/// where they aren't don't match the encoding.
+#[derive(Clone, Copy, Debug)]
+pub struct Encoding {
+    /// The width of the instruction word in bits
+    pub width: u32,
+    pub fields: &'static [BitField],
+}
+
+/// The bits `high` down to `low`, both included, of an instruction word.
+#[derive(Clone, Copy, Debug)]
+pub struct BitField {
+    pub name: &'static str,
+    pub high: u32,
+    pub low: u32,
+    pub value: FieldValue,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldValue {
+    /// The operand at this index
+    Operand(usize),
+    Fixed(u64),
+}
+
+impl BitField {
+    pub const fn width(&self) -> u32 {
+        self.high - self.low + 1
+    }
+
+    /// The bits of the field, in place in the word.
+    pub const fn mask(&self) -> u64 {
+        (u64::MAX >> (64 - self.width())) << self.low
+    }
+}
+
+impl Encoding {
+    /// Checks that the fields fit in the word without overlapping, that the
+    /// fixed bits fit in their field, and that each of the `operands`
+    /// is held by exactly one field.
+    ///
+    /// Can be evaluated at compile time, to reject invalid encodings early.
+    pub const fn check(&self, operands: usize) -> Result<(), &'static str> {
+        if self.width == 0 || self.width > 64 {
+            return Err("The word must be between 1 and 64 bits wide");
+        }
+
+        let mut used = 0;
+        let mut i = 0;
+        while i < self.fields.len() {
+            let field = &self.fields[i];
+            if field.low > field.high {
+                return Err("A field ends before it starts");
+            }
+            if field.high >= self.width {
+                return Err("A field doesn't fit in the word");
+            }
+            if used & field.mask() != 0 {
+                return Err("Fields overlap");
+            }
+            used |= field.mask();
+
+            match field.value {
+                FieldValue::Fixed(value) if value > field.mask() >> field.low => {
+                    return Err("Fixed bits don't fit in their field");
+                }
+                FieldValue::Operand(operand) if operand >= operands => {
+                    return Err("A field refers to a missing operand");
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        let mut operand = 0;
+        while operand < operands {
+            let mut count = 0;
+            let mut i = 0;
+            while i < self.fields.len() {
+                if let FieldValue::Operand(field_operand) = self.fields[i].value
+                    && field_operand == operand
+                {
+                    count += 1;
+                }
+                i += 1;
+            }
+            if count != 1 {
+                return Err("Each operand must be held by exactly one field");
+            }
+            operand += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Places `operands` in their fields, along with the fixed bits. Negative
+    /// operands are stored in two's complement.
+    pub fn encode(&self, operands: &[isize]) -> Result<u64, Box<dyn Error>> {
+        let mut word = 0;
+        for field in self.fields {
+            let value = match field.value {
+                FieldValue::Fixed(value) => value,
+                FieldValue::Operand(operand) => {
+                    let value = operands[operand];
+                    let width = field.width();
+                    let fits = if value < 0 {
+                        width == 64 || value >= -(1 << (width - 1))
+                    } else {
+                        width == 64 || value < 1 << width
+                    };
+                    if !fits {
+                        return Err(format!(
+                            "{value} doesn't fit in field {}[{}:{}]",
+                            field.name, field.high, field.low
+                        )
+                        .into());
+                    }
+                    value as u64
+                }
+            };
+            word |= (value << field.low) & field.mask();
+        }
+        Ok(word)
+    }
+
+    /// Whether the fixed bits of `word` are those of this encoding, and the
+    /// bits outside of every field are zero.
+    pub fn matches(&self, word: u64) -> bool {
+        let used = self
+            .fields
+            .iter()
+            .fold(0, |used, field| used | field.mask());
+        word & !used == 0
+            && self.fields.iter().all(|field| match field.value {
+                FieldValue::Fixed(value) => (word & field.mask()) >> field.low == value,
+                FieldValue::Operand(_) => true,
+            })
+    }
+
+    /// Extracts the operands from `word`, as the unsigned value of their field
+    /// along with its width, or `None` if it isn't encoded this way.
+    pub fn decode(&self, word: u64, operands: usize) -> Option<Vec<(u64, u32)>> {
+        if !self.matches(word) {
+            return None;
+        }
+
+        let mut decoded = vec![(0, 0); operands];
+        for field in self.fields {
+            if let FieldValue::Operand(operand) = field.value {
+                decoded[operand] = ((word & field.mask()) >> field.low, field.width());
+            }
+        }
+        Some(decoded)
+    }
+}
+
+/// Returns the index of `name` in `names`, to refer to operands by name.
+///
+/// Panics if it's missing, which fails the compilation in a constant.
+pub const fn operand_index(name: &str, names: &[&str]) -> usize {
+    let mut i = 0;
+    while i < names.len() {
+        if equal(names[i].as_bytes(), name.as_bytes()) {
+            return i;
+        }
+        i += 1;
+    }
+    panic!("A field refers to an unknown operand")
+}
+
+const fn equal(left: &[u8], right: &[u8]) -> bool {
+    if left.len() != right.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < left.len() {
+        if left[i] != right[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Interprets the `width` low bits of `value` as a two's complement number.
+pub fn sign_extend(value: u64, width: u32) -> isize {
+    let shift = 64 - width;
+    ((value << shift) as i64 >> shift) as isize
+}
@@ -0,0 +1,111 @@
+use crate::arch_def::{Architecture, Instruction};
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// The instructions of an architecture by mnemonic, to look them up without
+/// going through all of [`Instruction::enumerate`] for each statement.
+///
+/// Built the first time it's needed, see [`InstructionIndex::get`].
+pub struct InstructionIndex<A: Architecture> {
+    /// The overloads of each mnemonic by their number of operands, in the
+    /// order of [`Instruction::enumerate`]
+    overloads: HashMap<&'static str, HashMap<usize, Vec<A::Instruction>>>,
+    /// The mnemonics of the instructions and pseudo-instructions, by their
+    /// lowercase version, in order
+    mnemonics: HashMap<String, Vec<&'static str>>,
+}
+
+impl<A: Architecture> InstructionIndex<A> {
+    /// The index of `A`, built once per thread.
+    pub fn get() -> &'static Self {
+        thread_local! {
+            static INDICES: RefCell<HashMap<TypeId, &'static dyn Any>> = RefCell::default();
+        }
+
+        INDICES.with_borrow_mut(|indices| {
+            let index = indices
+                .entry(TypeId::of::<A>())
+                .or_insert_with(|| Box::leak(Box::new(Self::build())));
+            index
+                .downcast_ref()
+                .expect("Indices are stored by the type of their architecture")
+        })
+    }
+
+    fn build() -> Self {
+        let mut overloads = HashMap::<_, HashMap<_, Vec<_>>>::new();
+        let mut mnemonics = HashMap::<_, Vec<_>>::new();
+        for instruction in A::Instruction::enumerate() {
+            let name = instruction.name();
+            let arity = instruction.operands().into_iter().count();
+            overloads
+                .entry(name)
+                .or_default()
+                .entry(arity)
+                .or_default()
+                .push(*instruction);
+            mnemonics
+                .entry(name.to_ascii_lowercase())
+                .or_default()
+                .push(name);
+        }
+        for &name in A::pseudo_instructions() {
+            mnemonics
+                .entry(name.to_ascii_lowercase())
+                .or_default()
+                .push(name);
+        }
+
+        Self {
+            overloads,
+            mnemonics,
+        }
+    }
+
+    /// The instructions named `name` that take `arity` operands, in order.
+    pub fn overloads(&self, name: &str, arity: usize) -> &[A::Instruction] {
+        self.overloads
+            .get(name)
+            .and_then(|overloads| overloads.get(&arity))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// The mnemonic of an instruction or pseudo-instruction written as `name`,
+    /// preferring an exact match when ignoring case.
+    pub fn mnemonic(&self, name: &str, ignore_case: bool) -> Option<&'static str> {
+        let mnemonics = self.mnemonics.get(&name.to_ascii_lowercase())?;
+        let exact = mnemonics.iter().find(|mnemonic| **mnemonic == name);
+        exact.or(mnemonics.first().filter(|_| ignore_case)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architectures::test_arch::{TestArch, TestInstructions};
+
+    #[test]
+    fn finds_overloads_by_their_number_of_operands() {
+        let index = InstructionIndex::<TestArch>::get();
+        assert!(matches!(
+            index.overloads("addi", 3),
+            [TestInstructions::Addi]
+        ));
+        assert!(matches!(
+            index.overloads("addi", 2),
+            [TestInstructions::AddiImplicit]
+        ));
+        assert!(index.overloads("addi", 1).is_empty());
+        assert!(index.overloads("nop", 0).is_empty());
+    }
+
+    #[test]
+    fn finds_mnemonics_ignoring_case_if_asked() {
+        let index = InstructionIndex::<TestArch>::get();
+        assert_eq!(index.mnemonic("addi", false), Some("addi"));
+        assert_eq!(index.mnemonic("ADDI", false), None);
+        assert_eq!(index.mnemonic("ADDI", true), Some("addi"));
+        assert_eq!(index.mnemonic("nop", true), None);
+    }
+}
@@ -0,0 +1,350 @@
+/// Defines an architecture from a table of its instructions, generating the
+/// types that implement [`Architecture`](crate::arch_def::Architecture),
+/// [`Instruction`](crate::arch_def::Instruction),
+/// [`OperandKind`](crate::arch_def::OperandKind) and
+/// [`Symbol`](crate::arch_def::Symbol).
+///
+/// ```text
+/// define_architecture! {
+///     pub architecture Toy {
+///         // Optional hooks of the Architecture trait
+///     }
+///     symbols ToySymbol {
+///         Reg = "r" 0..8,
+///     }
+///     operand_kinds ToyOperandKind {
+///         Reg = symbol Reg,
+///         Imm8s = signed 8,
+///         Imm8u = unsigned 8,
+///     }
+///     instructions ToyInstruction: u16 {
+///         Add = "add" (Reg rd, Reg ra, Reg rb) => [op[15:12] = 0, rd[11:9], ra[8:6], rb[2:0]],
+///         Movi = "movi" (Reg rd, Imm8s imm) => [op[15:12] = 5, rd[11:9], imm[7:0]],
+///         Halt = "halt" () => [op[15:0] = 0xffff],
+///         Mul = "mul" (Reg rd, Reg ra, Reg rb) => [op[15:12] = 8, rd[11:9], ra[8:6], rb[2:0]] in mul,
+///         Swap = "swap" (Reg ra, Reg rb) => [op[15:12] = 9, ra[11:9], rb[8:6]] if ra != rb,
+///     }
+/// }
+/// ```
+///
+/// Each symbol variant holds the number following its prefix, which must be
+/// in the given range. Operands are parsed into an `isize`: symbols into their
+/// number and values after checking they fit in the given number of bits.
+///
+/// Each instruction is a variant, its mnemonic, its operands with their name,
+/// and its encoding as the bit fields of a word of the given type. Fields named
+/// after an operand hold it, and the others hold fixed bits, see
+/// [`Encoding`](crate::arch_def::encoding::Encoding). Encodings are checked at
+/// compile time, and also used to decode instruction words into the first
+/// instruction they match.
+/// Variants may share a mnemonic to overload it, and may be tagged with the
+/// extension they belong to, which must be declared by the `extensions` hook.
+/// They may end with a constraint on their operands, a boolean expression of
+/// their names, which [`Instruction::validate`](crate::arch_def::Instruction::validate)
+/// checks.
+///
+/// Words are stored in the order given by the `endianness` hook, little endian
+/// by default. Their size is the `instruction_word_size` of the architecture,
+/// so that hook can't be given.
+#[macro_export]
+macro_rules! define_architecture {
+    (
+        $vis:vis architecture $arch:ident { $($hooks:tt)* }
+        symbols $symbol:ident {
+            $($symbol_variant:ident = $prefix:literal $range:expr),+ $(,)?
+        }
+        operand_kinds $operand_kind:ident {
+            $($kind:ident = $class:ident $class_argument:tt),+ $(,)?
+        }
+        instructions $instruction:ident: $word:ty {
+            $(
+                $variant:ident = $name:literal ($($operand:ident $binding:ident),*) => [
+                    $($field:ident [$high:literal : $low:literal] $(= $fixed:literal)?),* $(,)?
+                ] $(in $extension:ident)? $(if $constraint:expr)?
+            ),+ $(,)?
+        }
+    ) => {
+        #[derive(Clone, Debug)]
+        $vis enum $arch {}
+
+        impl $crate::arch_def::Architecture for $arch {
+            type Instruction = $instruction;
+            type OperandKind = $operand_kind;
+            type Symbol = $symbol;
+
+            fn instruction_word_size() -> usize {
+                size_of::<$word>()
+            }
+
+            $($hooks)*
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        $vis enum $symbol {
+            $($symbol_variant(u8)),+
+        }
+
+        impl $crate::arch_def::Symbol<$arch> for $symbol {
+            fn parse(symbol: &str) -> Result<Self, Box<dyn std::error::Error>> {
+                $(
+                    if let Some(number) = symbol.strip_prefix($prefix)
+                        && let Ok(number) = number.parse::<u8>()
+                        && ($range).contains(&number)
+                    {
+                        return Ok(Self::$symbol_variant(number));
+                    }
+                )+
+                Err(format!("Invalid symbol: {symbol}").into())
+            }
+
+            fn enumerate() -> impl IntoIterator<Item = Self> {
+                let mut symbols = vec![];
+                $(symbols.extend(($range).map(Self::$symbol_variant));)+
+                symbols
+            }
+        }
+
+        impl std::fmt::Display for $symbol {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $(Self::$symbol_variant(number) => write!(f, "{}{number}", $prefix)),+
+                }
+            }
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        $vis enum $operand_kind {
+            $($kind),+
+        }
+
+        impl $crate::arch_def::OperandKind<$arch> for $operand_kind {
+            type Operand = isize;
+
+            fn parse(
+                &self,
+                plausible_operator: $crate::assembler::passes::parse::PlausibleOperator<$arch>,
+            ) -> Result<isize, Box<dyn std::error::Error>> {
+                match self {
+                    $(Self::$kind => $crate::define_architecture!(
+                        @parse $symbol, plausible_operator, $class $class_argument
+                    )),+
+                }
+            }
+
+            fn format(&self, operand: &isize) -> String {
+                match self {
+                    $(Self::$kind => $crate::define_architecture!(
+                        @format $symbol, operand, $class $class_argument
+                    )),+
+                }
+            }
+        }
+
+        impl $operand_kind {
+            /// Turns the bits of a field back into an operand.
+            // The width is unused when no operand kind is signed
+            #[allow(unused_variables)]
+            fn decode(&self, value: u64, width: u32) -> isize {
+                match self {
+                    $(Self::$kind => $crate::define_architecture!(
+                        @decode value, width, $class $class_argument
+                    )),+
+                }
+            }
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        $vis enum $instruction {
+            $($variant),+
+        }
+
+        impl $instruction {
+            const ALL: &'static [Self] = &[$(Self::$variant),+];
+
+            /// The encoding of each instruction, in the same order as [`Self::ALL`].
+            const ENCODINGS: &'static [$crate::arch_def::encoding::Encoding] = &[$({
+                // Unused when no field holds an operand
+                #[allow(dead_code)]
+                const OPERANDS: &[&str] = &[$(stringify!($binding)),*];
+                $crate::arch_def::encoding::Encoding {
+                    width: <$word>::BITS,
+                    fields: &[$(
+                        $crate::define_architecture!(@field OPERANDS, $field, $high, $low $(, $fixed)?)
+                    ),*],
+                }
+            }),+];
+
+            fn encoding(&self) -> &'static $crate::arch_def::encoding::Encoding {
+                &Self::ENCODINGS[*self as usize]
+            }
+        }
+
+        // Rejects invalid encodings at compile time
+        $(const _: () = if let Err(message) = $instruction::ENCODINGS[$instruction::$variant as usize]
+            .check({
+                const OPERANDS: &[&str] = &[$(stringify!($binding)),*];
+                OPERANDS.len()
+            })
+        {
+            panic!("{}", message)
+        };)+
+
+        impl $crate::arch_def::Instruction<$arch> for $instruction {
+            fn name(&self) -> &str {
+                match self {
+                    $(Self::$variant => $name),+
+                }
+            }
+
+            fn operands(&self) -> impl IntoIterator<Item = $operand_kind> {
+                match self {
+                    $(Self::$variant => vec![$($operand_kind::$operand),*]),+
+                }
+            }
+
+            fn emit(&self, operands: impl IntoIterator<Item = isize>) -> impl IntoIterator<Item = u8> {
+                let operands = Vec::from_iter(operands);
+                let word = self
+                    .encoding()
+                    .encode(&operands)
+                    .unwrap_or_else(|message| panic!("Can't encode {}: {message}", self.name()));
+                <$arch as $crate::arch_def::Architecture>::endianness()
+                    .bytes(word, size_of::<$word>())
+                    .unwrap_or_else(|message| panic!("Can't encode {}: {message}", self.name()))
+            }
+
+            fn enumerate() -> impl IntoIterator<Item = &'static Self> {
+                Self::ALL
+            }
+
+            fn extension(&self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => $crate::define_architecture!(@extension $($extension)?)),+
+                }
+            }
+
+            // Unused when no instruction has a constraint
+            #[allow(unused_variables)]
+            fn validate(&self, operands: &[isize]) -> Result<(), Box<dyn std::error::Error>> {
+                match self {
+                    $(Self::$variant => $crate::define_architecture!(
+                        @constraint $name, operands, ($($binding),*) $(, $constraint)?
+                    )),+
+                }
+            }
+
+            fn size(
+                &self,
+                _operands: &[$crate::assembler::passes::parse::PlausibleOperator<$arch>],
+            ) -> Option<usize> {
+                Some(size_of::<$word>())
+            }
+
+            fn decode(bytes: &[u8]) -> Option<(Self, std::rc::Rc<[isize]>)> {
+                let word = <$arch as $crate::arch_def::Architecture>::endianness()
+                    .value(bytes.get(..size_of::<$word>())?);
+                Self::ALL.iter().find_map(|instruction| {
+                    let kinds = Vec::from_iter($crate::arch_def::Instruction::operands(instruction));
+                    let fields = instruction.encoding().decode(word, kinds.len())?;
+                    let operands = kinds
+                        .iter()
+                        .zip(fields)
+                        .map(|(kind, (value, width))| kind.decode(value, width))
+                        .collect();
+                    Some((*instruction, operands))
+                })
+            }
+        }
+    };
+
+    (@parse $symbol:ident, $operator:ident, symbol $variant:ident) => {
+        match $operator {
+            $crate::assembler::passes::parse::PlausibleOperator::Symbol($symbol::$variant(number)) => {
+                Ok(number as isize)
+            }
+            _ => Err(concat!("Expected a ", stringify!($variant), " symbol").into()),
+        }
+    };
+    (@parse $symbol:ident, $operator:ident, signed $bits:literal) => {
+        match $operator {
+            $crate::assembler::passes::parse::PlausibleOperator::Value(value)
+                if (-(1 << ($bits - 1))..1 << ($bits - 1)).contains(&value) =>
+            {
+                Ok(value)
+            }
+            $crate::assembler::passes::parse::PlausibleOperator::Value(value) => {
+                Err(format!("{value} doesn't fit in a signed {}-bit immediate", $bits).into())
+            }
+            _ => Err("Expected a value".into()),
+        }
+    };
+    (@parse $symbol:ident, $operator:ident, unsigned $bits:literal) => {
+        match $operator {
+            $crate::assembler::passes::parse::PlausibleOperator::Value(value)
+                if (0..1 << $bits).contains(&value) =>
+            {
+                Ok(value)
+            }
+            $crate::assembler::passes::parse::PlausibleOperator::Value(value) => {
+                Err(format!("{value} doesn't fit in an unsigned {}-bit immediate", $bits).into())
+            }
+            _ => Err("Expected a value".into()),
+        }
+    };
+
+    (@format $symbol:ident, $operand:ident, symbol $variant:ident) => {
+        $symbol::$variant(*$operand as u8).to_string()
+    };
+    (@format $symbol:ident, $operand:ident, $class:ident $class_argument:tt) => {
+        $operand.to_string()
+    };
+
+    (@decode $value:ident, $width:ident, signed $bits:literal) => {
+        $crate::arch_def::encoding::sign_extend($value, $width)
+    };
+    (@decode $value:ident, $width:ident, $class:ident $class_argument:tt) => {
+        $value as isize
+    };
+
+    (@constraint $name:literal, $operands:ident, ($($binding:ident),*)) => {
+        Ok(())
+    };
+    (@constraint $name:literal, $operands:ident, ($($binding:ident),*), $constraint:expr) => {{
+        // The constraint may only use some of the operands
+        #[allow(unused_variables)]
+        let &[$($binding),*] = $operands else {
+            return Err(format!("Wrong number of operands for {}", $name).into());
+        };
+        if $constraint {
+            Ok(())
+        } else {
+            Err(concat!("Expected ", stringify!($constraint)).into())
+        }
+    }};
+
+    (@extension) => {
+        None
+    };
+    (@extension $extension:ident) => {
+        Some(stringify!($extension))
+    };
+
+    (@field $operands:ident, $field:ident, $high:literal, $low:literal, $fixed:literal) => {
+        $crate::arch_def::encoding::BitField {
+            name: stringify!($field),
+            high: $high,
+            low: $low,
+            value: $crate::arch_def::encoding::FieldValue::Fixed($fixed),
+        }
+    };
+    (@field $operands:ident, $field:ident, $high:literal, $low:literal) => {
+        $crate::arch_def::encoding::BitField {
+            name: stringify!($field),
+            high: $high,
+            low: $low,
+            value: $crate::arch_def::encoding::FieldValue::Operand(
+                $crate::arch_def::encoding::operand_index(stringify!($field), $operands),
+            ),
+        }
+    };
+}
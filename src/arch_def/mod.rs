@@ -1,13 +1,197 @@
 use crate::assembler::passes::parse::PlausibleOperator;
+use crate::assembler::passes::parse_operands::Operands;
 use std::error::Error;
 
-pub trait Architecture: Clone {
+pub mod dynamic;
+pub mod encoding;
+pub mod index;
+mod macros;
+pub mod registry;
+pub mod validate;
+
+pub use validate::validate;
+
+pub trait Architecture: Clone + 'static {
     type Instruction: Instruction<Self>;
     type OperandKind: OperandKind<Self>;
     type Symbol: Symbol<Self>;
+
+    /// Which names are matched regardless of case by default.
+    fn case_sensitivity() -> CaseSensitivity {
+        CaseSensitivity::Sensitive
+    }
+
+    /// The names of the optional extensions of the architecture, which
+    /// instructions can belong to, see [`Instruction::extension`].
+    fn extensions() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Other names for symbols, as `(alias, symbol)` pairs like `("sp", "r7")`.
+    /// Programs can define their own with `.alias`, see
+    /// [`AliasPass`](crate::assembler::passes::aliases::AliasPass).
+    fn symbol_aliases() -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    /// The order in which the bytes of data words are stored.
+    fn endianness() -> Endianness {
+        Endianness::Little
+    }
+
+    /// The size in bytes of a data word, as emitted by `.word`.
+    fn word_size() -> usize {
+        2
+    }
+
+    /// The size in bytes of the units instructions are made of. Every instruction
+    /// is a whole number of them.
+    fn instruction_word_size() -> usize {
+        1
+    }
+
+    /// The number of bytes each address refers to, like the word size for
+    /// word-addressed memory. Labels, `.`, `.org` and alignments count in these.
+    fn address_unit() -> usize {
+        1
+    }
+
+    /// The names of the pseudo-instructions, which are accepted like instructions
+    /// and expanded by [`Architecture::expand_pseudo_instruction`].
+    fn pseudo_instructions() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Expands the pseudo-instruction `name` into real instructions.
+    ///
+    /// Operands that are only known after layout are given as identifiers or
+    /// expressions, and can be combined into new expressions. The expansion
+    /// may only depend on them being present, so its size is known beforehand.
+    fn expand_pseudo_instruction(
+        name: &str,
+        _operands: &[PlausibleOperator<Self>],
+    ) -> Result<Expansion<Self>, Box<dyn Error>> {
+        Err(format!("Unknown pseudo-instruction {name}").into())
+    }
+
+    /// Returns a longer sequence that does the same as `instruction`, for when
+    /// its operands don't fit, like a branch to a far away target. Only used
+    /// when relaxation is enabled, and `None` if the instruction can't be relaxed.
+    ///
+    /// References to `.`, in the operands and in the returned sequence, stand for
+    /// the address of `instruction`.
+    ///
+    /// `scratch` is the register the sequence may overwrite, if the driver picked
+    /// one, see [`RelaxationPass::with_scratch`]. Otherwise the architecture falls
+    /// back to one of its own, which it should document as clobbered.
+    ///
+    /// [`RelaxationPass::with_scratch`]: crate::assembler::passes::relaxation::RelaxationPass::with_scratch
+    fn relax(
+        _instruction: Self::Instruction,
+        _operands: &[PlausibleOperator<Self>],
+        _scratch: Option<Self::Symbol>,
+    ) -> Option<Expansion<Self>> {
+        None
+    }
+}
+
+/// Which names are matched regardless of case.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaseSensitivity {
+    #[default]
+    Sensitive,
+    /// Instruction names and symbols, like registers, ignore case.
+    InsensitiveMnemonics,
+    /// Labels, constants and directives ignore case as well.
+    Insensitive,
+}
+
+/// The extensions enabled when assembling, see [`Architecture::extensions`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Extensions {
+    #[default]
+    All,
+    Only(Vec<String>),
+}
+
+impl Extensions {
+    /// Parses an ISA like `sisa+mul+sys`: the name of the base architecture,
+    /// which is returned as is, followed by the extensions to enable.
+    pub fn parse<A: Architecture>(isa: &str) -> Result<(&str, Self), Box<dyn Error>> {
+        let mut parts = isa.split('+');
+        let base = parts.next().filter(|base| !base.is_empty());
+        let base = base.ok_or_else(|| format!("Missing base architecture in {isa}"))?;
+
+        let extensions = parts
+            .map(|extension| {
+                if !A::extensions().contains(&extension) {
+                    return Err(format!(
+                        "Unknown extension {extension}, expected one of: {}",
+                        A::extensions().join(", ")
+                    ));
+                }
+                Ok(extension.to_string())
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((base, Self::Only(extensions)))
+    }
+
+    /// Fails if `instruction` belongs to an extension that isn't enabled.
+    pub fn check<A: Architecture>(
+        &self,
+        instruction: &A::Instruction,
+    ) -> Result<(), Box<dyn Error>> {
+        match (self, instruction.extension()) {
+            (Self::Only(enabled), Some(extension))
+                if !enabled.iter().any(|enabled| enabled == extension) =>
+            {
+                Err(format!(
+                    "{} is part of the {extension} extension, which isn't enabled",
+                    instruction.name()
+                )
+                .into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The order in which the bytes of a value wider than a byte are stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Endianness {
+    /// Least significant byte first
+    #[default]
+    Little,
+    /// Most significant byte first
+    Big,
 }
 
-pub trait Instruction<Arch: Architecture + ?Sized>: Clone + Copy
+impl Endianness {
+    /// The `width` least significant bytes of `value`, in this order.
+    pub fn bytes(self, value: u64, width: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        if width > size_of::<u64>() {
+            return Err(format!("Can't store values in {width} bytes, at most 8 fit").into());
+        }
+        Ok(match self {
+            Self::Little => value.to_le_bytes()[..width].to_vec(),
+            Self::Big => value.to_be_bytes()[8 - width..].to_vec(),
+        })
+    }
+
+    /// The value stored in `bytes`, the inverse of [`Endianness::bytes`].
+    pub fn value(self, bytes: &[u8]) -> u64 {
+        let shift_in = |value: u64, byte: &u8| value << 8 | u64::from(*byte);
+        match self {
+            Self::Little => bytes.iter().rev().fold(0, shift_in),
+            Self::Big => bytes.iter().fold(0, shift_in),
+        }
+    }
+}
+
+/// The real instructions a pseudo-instruction expands into, with their operands.
+pub type Expansion<A> = Vec<(<A as Architecture>::Instruction, Vec<PlausibleOperator<A>>)>;
+
+pub trait Instruction<Arch: Architecture>: Clone + Copy
 where
     Self: 'static,
 {
@@ -18,9 +202,49 @@ where
         operands: impl IntoIterator<Item = <Arch::OperandKind as OperandKind<Arch>>::Operand>,
     ) -> impl IntoIterator<Item = u8>;
     fn enumerate() -> impl IntoIterator<Item = &'static Self>;
+
+    /// The extension the instruction belongs to, or `None` if it's always available.
+    fn extension(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Checks the constraints between `operands` that their kinds can't express,
+    /// like a destination that must differ from a source, or a register pair
+    /// that must start at an even register. Called after parsing the operands,
+    /// so [`Instruction::emit`] is only given operands that pass. Accepts all
+    /// operands by default.
+    ///
+    /// Overloads are picked by the kinds of their operands alone, as most are
+    /// only known after layout, so operands that break the constraint of the
+    /// overload picked are reported rather than trying the next one.
+    fn validate(
+        &self,
+        _operands: &[<Arch::OperandKind as OperandKind<Arch>>::Operand],
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// The number of bytes the instruction occupies with `operands`, to lay the
+    /// program out before emitting it. Operands that are only known after layout
+    /// are given as identifiers or expressions.
+    ///
+    /// Returns `None` by default, to emit the instruction with placeholders for
+    /// those instead, which is enough when its size doesn't depend on their value.
+    /// Variable-length encodings guess a size for them, and the layout is redone
+    /// with their actual value until the sizes settle.
+    fn size(&self, _operands: &[PlausibleOperator<Arch>]) -> Option<usize> {
+        None
+    }
+
+    /// Decodes the instruction at the start of `bytes`, along with its operands,
+    /// or returns `None` if they don't start with a known instruction. Only needed
+    /// to disassemble, so architectures that don't support it can keep the default.
+    fn decode(_bytes: &[u8]) -> Option<(Self, Operands<Arch>)> {
+        None
+    }
 }
 
-pub trait OperandKind<Arch: Architecture + ?Sized> {
+pub trait OperandKind<Arch: Architecture> {
     type Operand: Clone;
     fn parse(
         &self,
@@ -29,8 +253,29 @@ pub trait OperandKind<Arch: Architecture + ?Sized> {
     fn matches(&self, plausible_operator: &PlausibleOperator<Arch>) -> bool {
         self.parse(plausible_operator.clone()).is_ok()
     }
+    /// Writes `operand` the way it's written in the source, for disassembly.
+    fn format(&self, operand: &Self::Operand) -> String;
 }
 
-pub trait Symbol<Arch: Architecture + ?Sized>: Sized + Clone {
+pub trait Symbol<Arch: Architecture>: Sized + Clone {
     fn parse(symbol: &str) -> Result<Self, Box<dyn Error>>;
+
+    /// All the symbols, to try instructions with when validating the
+    /// architecture, see [`validate`]. Empty by default, which leaves out the
+    /// instructions taking symbols.
+    fn enumerate() -> impl IntoIterator<Item = Self> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_at_most_eight_bytes() {
+        assert_eq!(Endianness::Little.bytes(0x0102, 2).unwrap(), [2, 1]);
+        assert_eq!(Endianness::Big.bytes(0x0102, 2).unwrap(), [1, 2]);
+        assert!(Endianness::Big.bytes(0, 9).is_err());
+    }
 }
@@ -0,0 +1,172 @@
+use crate::arch_def::validate::Report;
+use crate::arch_def::{Architecture, CaseSensitivity, Extensions, Symbol};
+use crate::assembler::sections::Section;
+use crate::assembler::{AssemblerPass, AssemblerPasses};
+use crate::disassembler::DisassemblerPasses;
+use std::error::Error;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+
+/// Architectures that can be chosen by name at runtime, like in a driver
+/// taking `--arch sisa-i`.
+///
+/// Architectures are types, so each is registered behind a
+/// [`RegisteredArchitecture`] that runs the passes for it.
+#[derive(Default)]
+pub struct Registry {
+    architectures: Vec<Box<dyn RegisteredArchitecture>>,
+}
+
+impl Registry {
+    /// Registers `A` as `name`, described by `summary` when listing them.
+    pub fn register<A: Architecture>(&mut self, name: &'static str, summary: &'static str) {
+        self.add(Registered::<A>::new(name, summary));
+    }
+
+    /// Registers an architecture set up beforehand, like one whose ISA names
+    /// it differently, see [`Registered::with_isa_base`].
+    pub fn add(&mut self, architecture: impl RegisteredArchitecture + 'static) {
+        let name = architecture.name();
+        if self.get(name).is_some() {
+            panic!("Architecture {name} is already registered");
+        }
+        self.architectures.push(Box::new(architecture));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn RegisteredArchitecture> {
+        self.architectures
+            .iter()
+            .find(|architecture| architecture.name() == name)
+            .map(Box::as_ref)
+    }
+
+    /// The registered architectures, in order of registration.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn RegisteredArchitecture> {
+        self.architectures.iter().map(Box::as_ref)
+    }
+}
+
+/// How to assemble a program, as given to a driver.
+#[derive(Clone, Debug, Default)]
+pub struct AssemblerOptions {
+    pub file: Option<PathBuf>,
+    pub include_paths: Vec<PathBuf>,
+    pub relax: bool,
+    /// The register relaxed instructions may overwrite, instead of the one
+    /// the architecture uses by default, see [`Architecture::relax`]
+    pub relax_scratch: Option<String>,
+    /// Overrides the case sensitivity of the architecture
+    pub case_sensitivity: Option<CaseSensitivity>,
+    /// The extensions to enable, like `sisa+mul+sys`, see [`Extensions::parse`].
+    /// The base architecture must be the registered one, named as registered
+    /// or as in its ISA, see [`Registered::with_isa_base`].
+    pub isa: Option<String>,
+}
+
+/// An architecture in a [`Registry`], with what a driver does with it.
+pub trait RegisteredArchitecture {
+    fn name(&self) -> &'static str;
+    fn summary(&self) -> &'static str;
+    fn extensions(&self) -> &'static [&'static str];
+    fn assemble(
+        &self,
+        source: &str,
+        options: &AssemblerOptions,
+    ) -> Result<Vec<Section>, Box<dyn Error>>;
+    fn disassemble(&self, bytes: &[u8]) -> Vec<String>;
+    /// Writes `section` as a memory file, see [`Section::memory_file`].
+    fn memory_file(&self, section: &Section) -> String;
+    /// Checks the definition of the architecture, see [`validate`](crate::arch_def::validate()).
+    fn validate(&self) -> Report;
+}
+
+/// Registers the architecture `A`.
+pub struct Registered<A: Architecture> {
+    name: &'static str,
+    summary: &'static str,
+    /// The name of the base architecture in an ISA like `sisa+mul`
+    isa_base: &'static str,
+    phantom_architecture: PhantomData<A>,
+}
+
+impl<A: Architecture> Registered<A> {
+    pub fn new(name: &'static str, summary: &'static str) -> Self {
+        Self {
+            name,
+            summary,
+            isa_base: name,
+            phantom_architecture: PhantomData,
+        }
+    }
+
+    /// Names the base architecture in ISAs, like `sisa` in `sisa+mul`, when
+    /// it isn't the registered name.
+    pub fn with_isa_base(mut self, isa_base: &'static str) -> Self {
+        self.isa_base = isa_base;
+        self
+    }
+}
+
+impl<A: Architecture> RegisteredArchitecture for Registered<A> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn summary(&self) -> &'static str {
+        self.summary
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        A::extensions()
+    }
+
+    fn assemble(
+        &self,
+        source: &str,
+        options: &AssemblerOptions,
+    ) -> Result<Vec<Section>, Box<dyn Error>> {
+        let mut assembler_passes =
+            AssemblerPasses::<A>::default().with_include_paths(options.include_paths.clone());
+        if let Some(file) = &options.file {
+            assembler_passes = assembler_passes.with_file(file);
+        }
+        if let Some(scratch) = &options.relax_scratch {
+            let scratch = A::symbol_aliases()
+                .iter()
+                .find(|(alias, _)| alias == scratch)
+                .map_or(scratch.as_str(), |(_, symbol)| symbol);
+            let scratch = A::Symbol::parse(scratch)?;
+            assembler_passes = assembler_passes.with_relaxation_scratch(scratch);
+        } else if options.relax {
+            assembler_passes = assembler_passes.with_relaxation();
+        }
+        if let Some(case_sensitivity) = options.case_sensitivity {
+            assembler_passes = assembler_passes.with_case_sensitivity(case_sensitivity);
+        }
+        if let Some(isa) = &options.isa {
+            let (base, extensions) = Extensions::parse::<A>(isa)?;
+            if base != self.isa_base && base != self.name {
+                return Err(format!(
+                    "Unknown base architecture {base}, expected {}",
+                    self.isa_base
+                )
+                .into());
+            }
+            assembler_passes = assembler_passes.with_extensions(extensions);
+        }
+
+        Ok(Vec::from_iter(assembler_passes.apply_all(source.chars())))
+    }
+
+    fn disassemble(&self, bytes: &[u8]) -> Vec<String> {
+        Vec::from_iter(DisassemblerPasses::<A>::default().apply_all(bytes.iter().copied()))
+    }
+
+    fn memory_file(&self, section: &Section) -> String {
+        section.memory_file::<A>()
+    }
+
+    fn validate(&self) -> Report {
+        crate::arch_def::validate::<A>()
+    }
+}
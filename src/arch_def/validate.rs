@@ -0,0 +1,507 @@
+use crate::arch_def::{Architecture, Instruction, OperandKind, Symbol};
+use crate::assembler::passes::parse::PlausibleOperator;
+use itertools::Itertools;
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+use std::panic::{self, AssertUnwindSafe};
+
+/// The mistakes found in the definition of an architecture by [`validate`].
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+}
+
+/// A mistake in the definition of an architecture. Instructions are given by
+/// their index in [`Instruction::enumerate`], along with their name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// `name` or `operands` panics.
+    Panics {
+        index: usize,
+        function: &'static str,
+        message: String,
+    },
+    /// The instruction is listed twice, the first time at `first`.
+    Duplicate {
+        index: usize,
+        name: String,
+        first: usize,
+    },
+    /// The instruction belongs to an extension the architecture doesn't declare.
+    UnknownExtension {
+        index: usize,
+        name: String,
+        extension: &'static str,
+    },
+    /// An overload that is never chosen, because the one at `overload` comes
+    /// first and accepts all of its operands.
+    UnreachableOverload {
+        index: usize,
+        name: String,
+        overload: usize,
+    },
+    /// An operand accepted by its kind, and by [`Instruction::validate`], doesn't
+    /// fit in the encoding: emitting the instruction panics, or the operand is
+    /// lost when decoding it.
+    FieldOverflow {
+        index: usize,
+        name: String,
+        operands: String,
+        message: String,
+    },
+    /// The instruction is encoded like another one, which it's decoded as.
+    Collision {
+        index: usize,
+        name: String,
+        operands: String,
+        decoded: String,
+    },
+    /// An alias of [`Architecture::symbol_aliases`] is already a symbol, or
+    /// its symbol doesn't exist.
+    InvalidAlias {
+        alias: &'static str,
+        symbol: &'static str,
+    },
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_ok() {
+            return write!(f, "No problems found");
+        }
+        write!(f, "{}", self.problems.iter().join("\n"))
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Panics {
+                index,
+                function,
+                message,
+            } => write!(f, "#{index}: {function} panics: {message}"),
+            Problem::Duplicate { index, name, first } => {
+                write!(f, "#{index} {name}: Duplicate of #{first}")
+            }
+            Problem::UnknownExtension {
+                index,
+                name,
+                extension,
+            } => write!(f, "#{index} {name}: Unknown extension {extension}"),
+            Problem::UnreachableOverload {
+                index,
+                name,
+                overload,
+            } => write!(
+                f,
+                "#{index} {name}: Unreachable, #{overload} accepts all of its operands"
+            ),
+            Problem::FieldOverflow {
+                index,
+                name,
+                operands,
+                message,
+            } => write!(f, "#{index} {}: {message}", statement(name, operands)),
+            Problem::Collision {
+                index,
+                name,
+                operands,
+                decoded,
+            } => write!(
+                f,
+                "#{index} {}: Decoded as {decoded}",
+                statement(name, operands)
+            ),
+            Problem::InvalidAlias { alias, symbol } => {
+                write!(f, "Alias {alias}: Invalid alias of {symbol}")
+            }
+        }
+    }
+}
+
+/// Checks the definition of `A` for mistakes, like instructions listed twice,
+/// encodings that collide or fields too narrow for their operands.
+///
+/// Instructions are tried with sample operands: the symbols of
+/// [`Symbol::enumerate`], and values at the edges of each width. Panics are
+/// caught and reported as problems.
+///
+/// ```
+/// use nara_assembler_infrastructure::arch_def::validate;
+/// use nara_assembler_infrastructure::architectures::sisa_i::SisaI;
+///
+/// let report = validate::<SisaI>();
+/// assert!(report.is_ok(), "{report}");
+/// ```
+pub fn validate<A: Architecture>() -> Report {
+    let mut problems = vec![];
+
+    for &(alias, symbol) in A::symbol_aliases() {
+        if A::Symbol::parse(alias).is_ok() || A::Symbol::parse(symbol).is_err() {
+            problems.push(Problem::InvalidAlias { alias, symbol });
+        }
+    }
+
+    let samples = samples::<A>();
+    let mut entries = vec![];
+    for (index, instruction) in A::Instruction::enumerate().into_iter().enumerate() {
+        let name = match catch(|| instruction.name().to_string()) {
+            Ok(name) => name,
+            Err(message) => {
+                problems.push(Problem::Panics {
+                    index,
+                    function: "name",
+                    message,
+                });
+                continue;
+            }
+        };
+        let kinds = match catch(|| Vec::from_iter(instruction.operands())) {
+            Ok(kinds) => kinds,
+            Err(message) => {
+                problems.push(Problem::Panics {
+                    index,
+                    function: "operands",
+                    message,
+                });
+                continue;
+            }
+        };
+
+        if let Some(extension) = instruction.extension()
+            && !A::extensions().contains(&extension)
+        {
+            problems.push(Problem::UnknownExtension {
+                index,
+                name: name.clone(),
+                extension,
+            });
+        }
+
+        let accepted = kinds
+            .iter()
+            .map(|kind| {
+                let accepted = samples.iter().positions(|sample| kind.matches(sample));
+                accepted.collect()
+            })
+            .collect();
+        entries.push(Entry {
+            index,
+            instruction: *instruction,
+            name,
+            kinds,
+            accepted,
+        });
+    }
+
+    for (position, entry) in entries.iter().enumerate() {
+        if let Some(problem) = check_overloads(entry, &entries[..position], &samples) {
+            problems.push(problem);
+        }
+        problems.extend(check_encoding(entry, &samples));
+    }
+
+    Report { problems }
+}
+
+/// An instruction that can be named, with the samples its operands accept.
+struct Entry<A: Architecture> {
+    index: usize,
+    instruction: A::Instruction,
+    name: String,
+    kinds: Vec<A::OperandKind>,
+    /// For each operand, the indices of the samples its kind accepts
+    accepted: Vec<Vec<usize>>,
+}
+
+/// Sample operators, to find out which operands are accepted.
+fn samples<A: Architecture>() -> Vec<PlausibleOperator<A>> {
+    let values = (1..=33).flat_map(|bits| {
+        let half: isize = 1 << (bits - 1);
+        [-half, half - 1, 2 * half - 1]
+    });
+    let values = [0].into_iter().chain(values).unique();
+
+    A::Symbol::enumerate()
+        .into_iter()
+        .map(PlausibleOperator::Symbol)
+        .chain(values.map(PlausibleOperator::Value))
+        .collect()
+}
+
+/// Compares `entry` to the earlier overloads of its name.
+fn check_overloads<A: Architecture>(
+    entry: &Entry<A>,
+    earlier: &[Entry<A>],
+    samples: &[PlausibleOperator<A>],
+) -> Option<Problem> {
+    // Without samples for an operand, nothing is known about what it accepts
+    if entry.accepted.iter().any(Vec::is_empty) {
+        return None;
+    }
+
+    let overload = earlier.iter().find(|overload| {
+        overload.name == entry.name
+            && overload.accepted.len() == entry.accepted.len()
+            && overload
+                .instruction
+                .extension()
+                .is_none_or(|extension| entry.instruction.extension() == Some(extension))
+            && entry
+                .accepted
+                .iter()
+                .zip(&overload.accepted)
+                .all(|(accepted, by_overload)| {
+                    accepted.iter().all(|sample| by_overload.contains(sample))
+                })
+    })?;
+
+    let same_bytes = || {
+        let sample = first_sample(entry);
+        let bytes = emit(entry, &sample, samples).ok()?;
+        Some(emit(overload, &sample, samples).ok()? == bytes)
+    };
+    if overload.accepted == entry.accepted && same_bytes() == Some(true) {
+        return Some(Problem::Duplicate {
+            index: entry.index,
+            name: entry.name.clone(),
+            first: overload.index,
+        });
+    }
+    Some(Problem::UnreachableOverload {
+        index: entry.index,
+        name: entry.name.clone(),
+        overload: overload.index,
+    })
+}
+
+/// Emits `entry` with each of the samples it accepts, and decodes it back.
+/// Reports at most one overflow and one collision.
+fn check_encoding<A: Architecture>(
+    entry: &Entry<A>,
+    samples: &[PlausibleOperator<A>],
+) -> Vec<Problem> {
+    if entry.accepted.iter().any(Vec::is_empty) {
+        return vec![];
+    }
+
+    // The first accepted sample for each operand, with one of them varied
+    let first = first_sample(entry);
+    let varied = entry
+        .accepted
+        .iter()
+        .enumerate()
+        .flat_map(|(operand, accepted)| {
+            accepted.iter().map({
+                let first = first.clone();
+                move |&sample| {
+                    let mut varied = first.clone();
+                    varied[operand] = sample;
+                    varied
+                }
+            })
+        });
+
+    let mut overflow = None;
+    let mut collision = None;
+    for sample in [first.clone()].into_iter().chain(varied) {
+        if overflow.is_some() && collision.is_some() {
+            break;
+        }
+
+        // Operands the instruction rejects are never emitted
+        let parsed = parse(entry, &sample, samples);
+        if !matches!(catch(|| entry.instruction.validate(&parsed)), Ok(Ok(()))) {
+            continue;
+        }
+
+        let operands = format_operands::<A>(entry.instruction, &parsed);
+        let bytes = match emit(entry, &sample, samples) {
+            Ok(bytes) => bytes,
+            Err(message) => {
+                overflow.get_or_insert_with(|| Problem::FieldOverflow {
+                    index: entry.index,
+                    name: entry.name.clone(),
+                    operands,
+                    message,
+                });
+                continue;
+            }
+        };
+
+        let Ok(Some((decoded, decoded_operands))) = catch(|| A::Instruction::decode(&bytes)) else {
+            continue;
+        };
+        let decoded_operands = format_operands::<A>(decoded, &decoded_operands);
+        let decoded_arity = decoded.operands().into_iter().count();
+        if decoded.name() != entry.name {
+            collision.get_or_insert_with(|| Problem::Collision {
+                index: entry.index,
+                name: entry.name.clone(),
+                operands,
+                decoded: statement(decoded.name(), &decoded_operands),
+            });
+        } else if decoded_arity == entry.kinds.len() && decoded_operands != operands {
+            overflow.get_or_insert_with(|| Problem::FieldOverflow {
+                index: entry.index,
+                name: entry.name.clone(),
+                operands,
+                message: format!(
+                    "Decoded as {}",
+                    statement(decoded.name(), &decoded_operands)
+                ),
+            });
+        }
+    }
+    overflow.into_iter().chain(collision).collect()
+}
+
+fn first_sample<A: Architecture>(entry: &Entry<A>) -> Vec<usize> {
+    entry.accepted.iter().map(|accepted| accepted[0]).collect()
+}
+
+/// The operands of `entry` for the given samples, which it accepts.
+fn parse<A: Architecture>(
+    entry: &Entry<A>,
+    sample: &[usize],
+    samples: &[PlausibleOperator<A>],
+) -> Vec<<A::OperandKind as OperandKind<A>>::Operand> {
+    entry
+        .kinds
+        .iter()
+        .zip(sample)
+        .map(|(kind, &sample)| {
+            kind.parse(samples[sample].clone())
+                .unwrap_or_else(|message| panic!("Accepted operand fails to parse: {message}"))
+        })
+        .collect()
+}
+
+fn emit<A: Architecture>(
+    entry: &Entry<A>,
+    sample: &[usize],
+    samples: &[PlausibleOperator<A>],
+) -> Result<Vec<u8>, String> {
+    let operands = parse(entry, sample, samples);
+    catch(|| Vec::from_iter(entry.instruction.emit(operands.clone())))
+}
+
+fn format_operands<A: Architecture>(
+    instruction: A::Instruction,
+    operands: &[<A::OperandKind as OperandKind<A>>::Operand],
+) -> String {
+    instruction
+        .operands()
+        .into_iter()
+        .zip(operands)
+        .map(|(kind, operand)| kind.format(operand))
+        .join(", ")
+}
+
+/// Joins an instruction name and its formatted operands, if it has any.
+fn statement(name: &str, operands: &str) -> String {
+    if operands.is_empty() {
+        name.to_string()
+    } else {
+        format!("{name} {operands}")
+    }
+}
+
+/// Runs `f`, returning the message it panics with if it does.
+fn catch<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| message(&*payload))
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::define_architecture;
+
+    define_architecture! {
+        architecture Broken {}
+        symbols BrokenSymbol {
+            Reg = "r" 0..8,
+        }
+        operand_kinds BrokenOperandKind {
+            Reg = symbol Reg,
+            Imm4s = signed 4,
+            Imm5s = signed 5,
+            Imm8s = signed 8,
+            Imm8u = unsigned 8,
+        }
+        // In pairs: listed twice, shadowed by a wider overload, fields too
+        // narrow for their immediate, and encoded alike
+        instructions BrokenInstruction: u16 {
+            Add = "add" (Reg rd, Reg ra) => [op[15:12] = 0, rd[11:9], ra[8:6]],
+            AddAgain = "add" (Reg rd, Reg ra) => [op[15:12] = 0, rd[11:9], ra[8:6]],
+            Addi = "addi" (Reg rd, Imm8s imm) => [op[15:12] = 1, rd[11:9], imm[7:0]],
+            AddiShort = "addi" (Reg rd, Imm4s imm) => [op[15:12] = 2, rd[11:9], imm[3:0]],
+            Movi = "movi" (Reg rd, Imm5s imm) => [op[15:12] = 3, rd[11:9], imm[3:0]],
+            Movu = "movu" (Reg rd, Imm8u imm) => [op[15:12] = 4, rd[11:9], imm[3:0]],
+            Nop = "nop" () => [op[15:0] = 0xffff],
+            Halt = "halt" () => [op[15:0] = 0xffff],
+        }
+    }
+
+    #[test]
+    fn finds_the_mistakes_of_a_broken_table() {
+        let report = validate::<Broken>();
+
+        assert_eq!(
+            report.problems,
+            [
+                Problem::Duplicate {
+                    index: 1,
+                    name: "add".to_string(),
+                    first: 0,
+                },
+                Problem::UnreachableOverload {
+                    index: 3,
+                    name: "addi".to_string(),
+                    overload: 2,
+                },
+                // The field holds 15, but decodes as a negative number
+                Problem::FieldOverflow {
+                    index: 4,
+                    name: "movi".to_string(),
+                    operands: "r0, 15".to_string(),
+                    message: "Decoded as movi r0, -1".to_string(),
+                },
+                // Emitting panics
+                Problem::FieldOverflow {
+                    index: 5,
+                    name: "movu".to_string(),
+                    operands: "r0, 31".to_string(),
+                    message: "Can't encode movu: 31 doesn't fit in field imm[3:0]".to_string(),
+                },
+                Problem::Collision {
+                    index: 7,
+                    name: "halt".to_string(),
+                    operands: String::new(),
+                    decoded: "nop".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            report.to_string().lines().last(),
+            Some("#7 halt: Decoded as nop")
+        );
+    }
+}
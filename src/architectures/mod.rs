@@ -0,0 +1,12 @@
+use crate::arch_def::registry::Registry;
+
+pub mod sisa_i;
+pub mod test_arch;
+
+/// Returns the registry of the architectures that come with the library.
+pub fn registry() -> Registry {
+    let mut registry = Registry::default();
+    sisa_i::register(&mut registry);
+    test_arch::register(&mut registry);
+    registry
+}
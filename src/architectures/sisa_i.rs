@@ -0,0 +1,191 @@
+use crate::arch_def::registry::{Registered, Registry};
+use crate::arch_def::{Architecture, CaseSensitivity, Expansion};
+use crate::assembler::expression::Expression;
+use crate::assembler::passes::parse::PlausibleOperator;
+use crate::assembler::passes::tokenize::Operator;
+use crate::define_architecture;
+use std::error::Error;
+
+define_architecture! {
+    pub architecture SisaI {
+        fn case_sensitivity() -> CaseSensitivity {
+            CaseSensitivity::InsensitiveMnemonics
+        }
+        // Multiplication and division, and interrupts and system registers
+        fn extensions() -> &'static [&'static str] {
+            &["mul", "sys"]
+        }
+        // r7 is the stack pointer and r6 the link register
+        fn symbol_aliases() -> &'static [(&'static str, &'static str)] {
+            &[("sp", "r7"), ("lr", "r6")]
+        }
+
+        fn pseudo_instructions() -> &'static [&'static str] {
+            &["nop", "mov", "li", "jz", "jnz", "jmp"]
+        }
+
+        fn expand_pseudo_instruction(name: &str, operands: &[PlausibleOperator<SisaI>]) -> Result<Expansion<SisaI>, Box<dyn Error>> {
+            let r0 = PlausibleOperator::Symbol(SisaISymbol::Reg(0));
+
+            match (name, operands) {
+                // and r0, r0, r0
+                ("nop", []) => Ok(vec![(SisaIInstruction::And, vec![r0.clone(), r0.clone(), r0])]),
+                ("mov", [rd, ra]) => Ok(vec![(SisaIInstruction::Addi, vec![rd.clone(), ra.clone(), PlausibleOperator::Value(0)])]),
+                // movi sign-extends its immediate, so small constants only need one instruction
+                ("li", [rd, PlausibleOperator::Value(value)]) if (-128..128).contains(value) => {
+                    Ok(vec![(SisaIInstruction::Movi, vec![rd.clone(), PlausibleOperator::Value(*value)])])
+                }
+                ("li", [rd, PlausibleOperator::Value(value)]) if !(-32768..65536).contains(value) => {
+                    Err(format!("Constant {value} doesn't fit in 16 bits").into())
+                }
+                ("li", [rd, value]) => {
+                    let value = value.to_expression().ok_or("li expects a value")?;
+                    Ok(vec![
+                        (SisaIInstruction::Movi, vec![rd.clone(), signed_byte(value.clone())]),
+                        (SisaIInstruction::Movhi, vec![rd.clone(), signed_byte(binary(Operator::ShiftRight, value, Expression::Value(8)))]),
+                    ])
+                }
+                // Branches to a label instead of an offset
+                ("jz", [ra, target]) => Ok(vec![(SisaIInstruction::Bz, vec![ra.clone(), branch_offset(target)?])]),
+                ("jnz", [ra, target]) => Ok(vec![(SisaIInstruction::Bnz, vec![ra.clone(), branch_offset(target)?])]),
+                // Branch if r0 is zero, and otherwise branch from the next instruction
+                ("jmp", [target]) => Ok(vec![
+                    (SisaIInstruction::Bz, vec![r0.clone(), branch_offset(target)?]),
+                    (SisaIInstruction::Bnz, vec![r0, branch_offset(target)?]),
+                ]),
+                _ => Err(format!("Invalid operands for {name}").into()),
+            }
+        }
+
+        // Jumps through the scratch register, r6 (lr) by default, which is overwritten
+        fn relax(instruction: SisaIInstruction, operands: &[PlausibleOperator<SisaI>], scratch: Option<SisaISymbol>) -> Option<Expansion<SisaI>> {
+            // Skip over the jump when the condition doesn't hold
+            let inverted = match instruction {
+                SisaIInstruction::Bz => SisaIInstruction::Bnz,
+                SisaIInstruction::Bnz => SisaIInstruction::Bz,
+                _ => return None,
+            };
+            let [ra, offset] = operands else { return None };
+
+            // PC + 2 + offset * 2
+            let target = binary(Operator::Plus, binary(Operator::Plus, Expression::Identifier(".".to_string()), instruction_length()), binary(Operator::Multiply, offset.to_expression()?, instruction_length()));
+            let scratch = PlausibleOperator::Symbol(scratch.unwrap_or(SisaISymbol::Reg(6)));
+
+            Some(vec![
+                (inverted, vec![ra.clone(), PlausibleOperator::Value(3)]),
+                (SisaIInstruction::Movi, vec![scratch.clone(), signed_byte(target.clone())]),
+                (SisaIInstruction::Movhi, vec![scratch.clone(), signed_byte(binary(Operator::ShiftRight, target, Expression::Value(8)))]),
+                (SisaIInstruction::Jalr, vec![scratch.clone(), scratch]),
+            ])
+        }
+    }
+    symbols SisaISymbol {
+        Reg = "r" 0..8,
+        Sys = "s" 0..8,
+    }
+    operand_kinds SisaIOperandKind {
+        Reg = symbol Reg,
+        Sreg = symbol Sys,
+        Imm6s = signed 6,
+        Imm8s = signed 8,
+        Imm8u = unsigned 8,
+    }
+    instructions SisaIInstruction: u16 {
+        And = "and" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 0, rd[11:9], ra[8:6], function[5:3] = 0, rb[2:0]],
+        Or = "or" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 0, rd[11:9], ra[8:6], function[5:3] = 1, rb[2:0]],
+        Xor = "xor" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 0, rd[11:9], ra[8:6], function[5:3] = 2, rb[2:0]],
+        Not = "not" (Reg rd, Reg ra) => [opcode[15:12] = 0, rd[11:9], ra[8:6], function[5:3] = 3],
+        Add = "add" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 0, rd[11:9], ra[8:6], function[5:3] = 4, rb[2:0]],
+        Sub = "sub" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 0, rd[11:9], ra[8:6], function[5:3] = 5, rb[2:0]],
+        Sha = "sha" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 0, rd[11:9], ra[8:6], function[5:3] = 6, rb[2:0]],
+        Shl = "shl" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 0, rd[11:9], ra[8:6], function[5:3] = 7, rb[2:0]],
+        Cmplt = "cmplt" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 1, rd[11:9], ra[8:6], function[5:3] = 0, rb[2:0]],
+        Cmple = "cmple" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 1, rd[11:9], ra[8:6], function[5:3] = 1, rb[2:0]],
+        Cmpeq = "cmpeq" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 1, rd[11:9], ra[8:6], function[5:3] = 3, rb[2:0]],
+        Cmpltu = "cmpltu" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 1, rd[11:9], ra[8:6], function[5:3] = 4, rb[2:0]],
+        Cmpleu = "cmpleu" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 1, rd[11:9], ra[8:6], function[5:3] = 5, rb[2:0]],
+        Addi = "addi" (Reg rd, Reg ra, Imm6s imm) => [opcode[15:12] = 2, rd[11:9], ra[8:6], imm[5:0]],
+        Ld = "ld" (Reg rd, Imm6s offset, Reg ra) => [opcode[15:12] = 3, rd[11:9], ra[8:6], offset[5:0]],
+        St = "st" (Imm6s offset, Reg ra, Reg rb) => [opcode[15:12] = 4, rb[11:9], ra[8:6], offset[5:0]],
+        Movi = "movi" (Reg rd, Imm8s imm) => [opcode[15:12] = 5, rd[11:9], high[8:8] = 0, imm[7:0]],
+        Movhi = "movhi" (Reg rd, Imm8s imm) => [opcode[15:12] = 5, rd[11:9], high[8:8] = 1, imm[7:0]],
+        Bz = "bz" (Reg ra, Imm8s offset) => [opcode[15:12] = 6, ra[11:9], not_zero[8:8] = 0, offset[7:0]],
+        Bnz = "bnz" (Reg ra, Imm8s offset) => [opcode[15:12] = 6, ra[11:9], not_zero[8:8] = 1, offset[7:0]],
+        In = "in" (Reg rd, Imm8u port) => [opcode[15:12] = 7, rd[11:9], out[8:8] = 0, port[7:0]],
+        Out = "out" (Imm8u port, Reg rb) => [opcode[15:12] = 7, rb[11:9], out[8:8] = 1, port[7:0]],
+        Jalr = "jalr" (Reg rd, Reg ra) => [opcode[15:12] = 10, rd[11:9], ra[8:6]],
+        Mul = "mul" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 8, rd[11:9], ra[8:6], function[5:3] = 0, rb[2:0]] in mul,
+        Mulh = "mulh" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 8, rd[11:9], ra[8:6], function[5:3] = 1, rb[2:0]] in mul,
+        Mulhu = "mulhu" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 8, rd[11:9], ra[8:6], function[5:3] = 2, rb[2:0]] in mul,
+        Div = "div" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 8, rd[11:9], ra[8:6], function[5:3] = 4, rb[2:0]] in mul,
+        Divu = "divu" (Reg rd, Reg ra, Reg rb) => [opcode[15:12] = 8, rd[11:9], ra[8:6], function[5:3] = 5, rb[2:0]] in mul,
+        Ei = "ei" () => [opcode[15:12] = 15, function[5:0] = 32] in sys,
+        Di = "di" () => [opcode[15:12] = 15, function[5:0] = 33] in sys,
+        Reti = "reti" () => [opcode[15:12] = 15, function[5:0] = 36] in sys,
+        Getiid = "getiid" (Reg rd) => [opcode[15:12] = 15, rd[11:9], function[5:0] = 40] in sys,
+        Rds = "rds" (Reg rd, Sreg sa) => [opcode[15:12] = 15, rd[11:9], sa[8:6], function[5:0] = 44] in sys,
+        Wrs = "wrs" (Sreg sd, Reg ra) => [opcode[15:12] = 15, sd[11:9], ra[8:6], function[5:0] = 48] in sys,
+        Halt = "halt" () => [opcode[15:12] = 15, function[11:0] = 4095] in sys,
+    }
+}
+
+/// Registers SISA-I as `sisa-i`.
+pub fn register(registry: &mut Registry) {
+    registry.add(
+        Registered::<SisaI>::new("sisa-i", "SISA-I, with the mul and sys extensions")
+            .with_isa_base("sisa"),
+    );
+}
+
+/// The offset of a branch to `target`, counted in words from the next instruction.
+fn branch_offset(
+    target: &PlausibleOperator<SisaI>,
+) -> Result<PlausibleOperator<SisaI>, Box<dyn Error>> {
+    let target = target.to_expression().ok_or("Expected a branch target")?;
+    let distance = binary(
+        Operator::Minus,
+        binary(
+            Operator::Minus,
+            target,
+            Expression::Identifier(".".to_string()),
+        ),
+        instruction_length(),
+    );
+    Ok(PlausibleOperator::from_expression(binary(
+        Operator::Divide,
+        distance,
+        instruction_length(),
+    )))
+}
+
+/// The number of addresses an instruction spans.
+fn instruction_length() -> Expression {
+    Expression::Value((SisaI::instruction_word_size() / SisaI::address_unit()) as isize)
+}
+
+fn binary(operator: Operator, left: Expression, right: Expression) -> Expression {
+    Expression::Binary(operator, Box::new(left), Box::new(right))
+}
+
+/// The low byte of `value`, as the signed immediate of movi and movhi.
+fn signed_byte(value: Expression) -> PlausibleOperator<SisaI> {
+    let byte = binary(Operator::BitAnd, value, Expression::Value(0xff));
+    let signed = binary(
+        Operator::Minus,
+        binary(Operator::BitXor, byte, Expression::Value(0x80)),
+        Expression::Value(0x80),
+    );
+    PlausibleOperator::from_expression(signed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch_def::validate;
+
+    #[test]
+    fn definition_is_valid() {
+        let report = validate::<SisaI>();
+        assert!(report.is_ok(), "{report}");
+    }
+}
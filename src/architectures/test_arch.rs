@@ -0,0 +1,245 @@
+use crate::arch_def::registry::Registry;
+use crate::arch_def::{Architecture, Instruction, OperandKind, Symbol};
+use crate::assembler::passes::parse::PlausibleOperator;
+use std::error::Error;
+use std::rc::Rc;
+
+/// A small architecture with 5-byte instructions, and a variable-length `li`,
+/// to exercise the assembler.
+#[derive(Clone, Debug)]
+pub enum TestArch {}
+
+#[derive(Clone, Copy, Debug)]
+pub enum TestInstructions {
+    Xor,
+    Addi,
+    AddiImplicit,
+    Halt,
+    Jump,
+    Li,
+}
+
+const TEST_INSTRUCTIONS: &[TestInstructions] = &[
+    TestInstructions::Xor,
+    TestInstructions::Addi,
+    TestInstructions::AddiImplicit,
+    TestInstructions::Halt,
+    TestInstructions::Jump,
+    TestInstructions::Li,
+];
+
+pub enum TestOperandKinds {
+    Register,
+    Immediate,
+}
+
+#[derive(Clone, Debug)]
+pub enum TestOperands {
+    Register(u8),
+    Immediate(i16),
+}
+
+#[derive(Clone, Debug)]
+pub enum TestSymbols {
+    Register(u8),
+}
+
+impl Architecture for TestArch {
+    type Instruction = TestInstructions;
+    type OperandKind = TestOperandKinds;
+    type Symbol = TestSymbols;
+}
+
+impl Instruction<TestArch> for TestInstructions {
+    fn name(&self) -> &str {
+        match self {
+            TestInstructions::Xor => "xor",
+            TestInstructions::Addi => "addi",
+            TestInstructions::AddiImplicit => "addi",
+            TestInstructions::Halt => "halt",
+            TestInstructions::Jump => "jump",
+            TestInstructions::Li => "li",
+        }
+    }
+
+    fn operands(&self) -> impl IntoIterator<Item = <TestArch as Architecture>::OperandKind> {
+        match self {
+            TestInstructions::Xor => vec![
+                TestOperandKinds::Register,
+                TestOperandKinds::Register,
+                TestOperandKinds::Register,
+            ],
+            TestInstructions::Addi => vec![
+                TestOperandKinds::Register,
+                TestOperandKinds::Register,
+                TestOperandKinds::Immediate,
+            ],
+            TestInstructions::AddiImplicit => {
+                vec![TestOperandKinds::Register, TestOperandKinds::Immediate]
+            }
+            TestInstructions::Halt => vec![],
+            TestInstructions::Jump => vec![TestOperandKinds::Immediate],
+            TestInstructions::Li => vec![TestOperandKinds::Register, TestOperandKinds::Immediate],
+        }
+    }
+
+    fn emit(
+        &self,
+        operands: impl IntoIterator<Item = TestOperands>,
+    ) -> impl IntoIterator<Item = u8> {
+        use TestOperands::{Immediate, Register};
+
+        match (self, Vec::from_iter(operands).as_slice()) {
+            (TestInstructions::Xor, &[Register(rd), Register(rs1), Register(rs2)]) => {
+                vec![0, rd, rs1, rs2, 0]
+            }
+            (TestInstructions::Addi, &[Register(rd), Register(rs1), Immediate(imm)]) => {
+                vec![1, rd, rs1, imm as u8, (imm >> 8) as u8]
+            }
+            (TestInstructions::AddiImplicit, &[Register(rd), Immediate(imm)]) => {
+                vec![1, rd, rd, imm as u8, (imm >> 8) as u8]
+            }
+            (TestInstructions::Halt, []) => vec![2, 0, 0, 0, 0],
+            (TestInstructions::Jump, &[Immediate(imm)]) => {
+                vec![3, imm as u8, (imm >> 8) as u8, 0, 0]
+            }
+            (TestInstructions::Li, &[Register(rd), Immediate(imm)]) => match i8::try_from(imm) {
+                Ok(imm) => vec![4, rd, imm as u8],
+                Err(_) => vec![5, rd, imm as u8, (imm >> 8) as u8],
+            },
+            (instruction, operands) => panic!(
+                "Invalid operands for {}: {operands:?}, which validate rejects",
+                instruction.name()
+            ),
+        }
+    }
+
+    /// Checks that each operand is of its kind, which is all [`Self::emit`] relies on.
+    fn validate(&self, operands: &[TestOperands]) -> Result<(), Box<dyn Error>> {
+        let kinds = Vec::from_iter(self.operands());
+        if operands.len() != kinds.len() {
+            return Err(format!("Expected {} operands", kinds.len()).into());
+        }
+        for (position, (kind, operand)) in kinds.iter().zip(operands).enumerate() {
+            match (kind, operand) {
+                (TestOperandKinds::Register, TestOperands::Register(_))
+                | (TestOperandKinds::Immediate, TestOperands::Immediate(_)) => {}
+                (TestOperandKinds::Register, _) => {
+                    return Err(format!("Operand {} must be a register", position + 1).into());
+                }
+                (TestOperandKinds::Immediate, _) => {
+                    return Err(format!("Operand {} must be an immediate", position + 1).into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn enumerate() -> impl IntoIterator<Item = &'static Self> {
+        TEST_INSTRUCTIONS
+    }
+
+    fn size(&self, operands: &[PlausibleOperator<TestArch>]) -> Option<usize> {
+        match (self, operands) {
+            (TestInstructions::Li, [_, PlausibleOperator::Value(imm)]) => {
+                Some(if i8::try_from(*imm).is_ok() { 3 } else { 4 })
+            }
+            // Guess the short form until the value is known
+            (TestInstructions::Li, _) => Some(3),
+            _ => None,
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Option<(Self, Rc<[TestOperands]>)> {
+        let register = |byte: u8| TestOperands::Register(byte);
+        let immediate =
+            |low: u8, high: u8| TestOperands::Immediate(i16::from_le_bytes([low, high]));
+
+        Some(match *bytes {
+            [0, rd, rs1, rs2, 0, ..] => (
+                TestInstructions::Xor,
+                Rc::new([register(rd), register(rs1), register(rs2)]),
+            ),
+            [1, rd, rs1, low, high, ..] => (
+                TestInstructions::Addi,
+                Rc::new([register(rd), register(rs1), immediate(low, high)]),
+            ),
+            [2, 0, 0, 0, 0, ..] => (TestInstructions::Halt, Rc::new([])),
+            [3, low, high, 0, 0, ..] => (TestInstructions::Jump, Rc::new([immediate(low, high)])),
+            [4, rd, imm, ..] => (
+                TestInstructions::Li,
+                Rc::new([register(rd), TestOperands::Immediate(imm as i8 as i16)]),
+            ),
+            [5, rd, low, high, ..] => (
+                TestInstructions::Li,
+                Rc::new([register(rd), immediate(low, high)]),
+            ),
+            _ => return None,
+        })
+    }
+}
+
+impl OperandKind<TestArch> for TestOperandKinds {
+    type Operand = TestOperands;
+
+    fn parse(
+        &self,
+        plausible_operator: PlausibleOperator<TestArch>,
+    ) -> Result<Self::Operand, Box<dyn Error>> {
+        match (self, plausible_operator) {
+            (Self::Register, PlausibleOperator::Symbol(TestSymbols::Register(register))) => {
+                Ok(TestOperands::Register(register))
+            }
+            (Self::Immediate, PlausibleOperator::Value(value)) => {
+                Ok(TestOperands::Immediate(value.try_into()?))
+            }
+            _ => Err("The provided operand can't be accepted".into()),
+        }
+    }
+
+    fn format(&self, operand: &TestOperands) -> String {
+        match operand {
+            TestOperands::Register(register) => format!("r{register}"),
+            TestOperands::Immediate(value) => value.to_string(),
+        }
+    }
+}
+
+impl Symbol<TestArch> for TestSymbols {
+    fn parse(symbol: &str) -> Result<Self, Box<dyn Error>> {
+        if let Some(register) = symbol.strip_prefix('r') {
+            Ok(Self::Register(register.parse()?))
+        } else {
+            Err(format!("Unparsable symbol: {}", symbol).into())
+        }
+    }
+
+    fn enumerate() -> impl IntoIterator<Item = Self> {
+        (0..=u8::MAX).map(Self::Register)
+    }
+}
+
+/// Registers the test architecture as `test`.
+pub fn register(registry: &mut Registry) {
+    registry.register::<TestArch>("test", "The test architecture of the demo");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch_def::validate;
+
+    #[test]
+    fn definition_is_valid() {
+        let report = validate::<TestArch>();
+        assert!(report.is_ok(), "{report}");
+    }
+
+    #[test]
+    fn rejects_operands_of_the_wrong_kind() {
+        let registers = [TestOperands::Register(1), TestOperands::Register(2)];
+        assert!(TestInstructions::Li.validate(&registers).is_err());
+        assert!(TestInstructions::Jump.validate(&registers).is_err());
+        assert!(TestInstructions::Halt.validate(&[]).is_ok());
+    }
+}
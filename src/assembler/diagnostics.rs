@@ -0,0 +1,83 @@
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+/// A line of source code, used to point diagnostics at their cause.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub file: Rc<str>,
+    pub line: usize,
+    /// The location that expanded this line, if it comes from a macro or repetition.
+    pub expansion: Option<Rc<Location>>,
+    /// The iteration of the repetition at this location that expanded a line.
+    pub iteration: Option<usize>,
+}
+
+impl Location {
+    pub const DEFAULT_FILE: &'static str = "<input>";
+
+    pub fn new(file: Rc<str>, line: usize) -> Self {
+        Self {
+            file,
+            line,
+            expansion: None,
+            iteration: None,
+        }
+    }
+
+    pub fn expanded_from(&self, expansion: &Location) -> Self {
+        Self {
+            file: self.file.clone(),
+            line: self.line,
+            expansion: Some(Rc::new(expansion.clone())),
+            iteration: None,
+        }
+    }
+
+    pub fn in_iteration(&self, iteration: usize) -> Self {
+        Self {
+            iteration: Some(iteration),
+            ..self.clone()
+        }
+    }
+}
+
+impl Default for Location {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_FILE.into(), 1)
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        if let Some(iteration) = self.iteration {
+            write!(f, ", iteration {iteration}")?;
+        }
+        if let Some(expansion) = &self.expansion {
+            write!(f, " (expanded from {expansion})")?;
+        }
+        Ok(())
+    }
+}
+
+/// How serious a diagnostic is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+}
+
+/// Aborts the assembly, reporting `message` at `location`.
+pub fn error(location: &Location, message: impl Display) -> ! {
+    panic!("{location}: {message}")
+}
+
+/// Reports `message` at `location`, aborting the assembly if it's an error.
+pub fn report(level: Level, location: &Location, message: impl Display) {
+    match level {
+        Level::Error => error(location, message),
+        Level::Warning => eprintln!("{location}: warning: {message}"),
+        Level::Note => eprintln!("{location}: note: {message}"),
+    }
+}
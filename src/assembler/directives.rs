@@ -0,0 +1,324 @@
+use crate::arch_def::Architecture;
+use crate::assembler::diagnostics::Level;
+use crate::assembler::passes::parse::PlausibleOperator;
+use crate::assembler::sections::SectionFlags;
+use std::error::Error;
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+#[derive(Clone)]
+pub enum Directive<A: Architecture> {
+    /// `.align n[, fill]`: pads with `fill` up to the next multiple of `2^n`
+    /// and `.balign n[, fill]`: pads with `fill` up to the next multiple of `n`.
+    Align { boundary: usize, fill: u8 },
+    /// `.org address[, fill]`: pads with `fill` until the location counter
+    /// reaches `address`. The location counter can't be moved backwards.
+    Org { address: usize, fill: u8 },
+    /// Raw padding, the result of laying out the directives above.
+    Fill { count: usize, value: u8 },
+    /// `.align`, `.balign`, `.org` or `.section` with arguments that refer to
+    /// symbols, like `.org RESET`: parsed again once they're resolved at layout,
+    /// so the symbols must be defined before.
+    Deferred {
+        name: String,
+        arguments: Rc<[PlausibleOperator<A>]>,
+    },
+    /// `.section name[, "flags"[, base]]`, or the `.text`, `.data` and `.bss` shorthands:
+    /// switches to the given section. Flags and base address may be omitted when the
+    /// section was already defined, and are always present once laid out.
+    Section {
+        name: String,
+        flags: Option<SectionFlags>,
+        base: Option<usize>,
+    },
+    /// `.equ name, value`: defines a constant symbol.
+    Equ {
+        name: String,
+        value: PlausibleOperator<A>,
+    },
+    /// `.byte value, ...` and `.word value, ...`: emits each value in `width` bytes,
+    /// in the order given by [`Architecture::endianness`].
+    Data {
+        width: usize,
+        values: Rc<[PlausibleOperator<A>]>,
+    },
+    /// `.scope [name]`: starts a scope, where labels are prefixed by `name.`.
+    Scope { name: Option<String> },
+    /// `.endscope`: ends the innermost scope.
+    EndScope,
+    /// `.assert condition[, message, ...]`, `.error message, ...`,
+    /// `.warning message, ...` and `.print message, ...`: reports a diagnostic
+    /// once the program is laid out, if there's no condition or it's zero.
+    /// The message is made of strings and values, which may reference labels.
+    Report {
+        level: Level,
+        condition: Option<PlausibleOperator<A>>,
+        message: Rc<[PlausibleOperator<A>]>,
+    },
+}
+
+impl<A: Architecture> Directive<A> {
+    pub fn parse(name: &str, arguments: &[PlausibleOperator<A>]) -> Result<Self, Box<dyn Error>> {
+        Ok(match (name, arguments) {
+            (".section", [PlausibleOperator::Identifier(section), rest @ ..]) => {
+                let (flags, base) = match rest {
+                    [] => (None, None),
+                    [PlausibleOperator::String(flags)] => (Some(SectionFlags::parse(flags)?), None),
+                    [
+                        PlausibleOperator::String(flags),
+                        PlausibleOperator::Value(base),
+                    ] => (Some(SectionFlags::parse(flags)?), Some(to_address(*base)?)),
+                    [PlausibleOperator::String(flags), base] if is_value(base) => {
+                        SectionFlags::parse(flags)?;
+                        return Ok(Self::deferred(name, arguments));
+                    }
+                    _ => return Err(format!("Invalid arguments for section {section}").into()),
+                };
+                Self::Section {
+                    name: section.clone(),
+                    flags,
+                    base,
+                }
+            }
+            (".equ", [PlausibleOperator::Identifier(symbol), value]) => Self::Equ {
+                name: symbol.clone(),
+                value: value.clone(),
+            },
+            (".scope", []) => Self::Scope { name: None },
+            (".scope", [PlausibleOperator::Identifier(name)]) => Self::Scope {
+                name: Some(name.clone()),
+            },
+            (".endscope", []) => Self::EndScope,
+            (".assert", [condition, message @ ..]) => {
+                if !is_value(condition) {
+                    return Err("Directive .assert expects a condition".into());
+                }
+                Self::Report {
+                    level: Level::Error,
+                    condition: Some(condition.clone()),
+                    message: parse_message(name, message)?,
+                }
+            }
+            (".error" | ".warning" | ".print", message) => {
+                if message.is_empty() {
+                    return Err(format!("Directive {name} expects a message").into());
+                }
+                Self::Report {
+                    level: match name {
+                        ".error" => Level::Error,
+                        ".warning" => Level::Warning,
+                        _ => Level::Note,
+                    },
+                    condition: None,
+                    message: parse_message(name, message)?,
+                }
+            }
+            (".text" | ".data" | ".bss", []) => Self::Section {
+                name: name.to_string(),
+                flags: None,
+                base: None,
+            },
+            (".byte" | ".word", values) => {
+                if values.is_empty() {
+                    return Err(format!("Directive {name} expects at least one value").into());
+                }
+                if !values.iter().all(is_value) {
+                    return Err(format!("Directive {name} only accepts values").into());
+                }
+                Self::Data {
+                    width: data_width::<A>(name).unwrap(),
+                    values: values.into(),
+                }
+            }
+            (".align" | ".balign" | ".org", arguments)
+                if arguments.iter().all(is_value)
+                    && !arguments
+                        .iter()
+                        .all(|argument| matches!(argument, PlausibleOperator::Value(_))) =>
+            {
+                Self::deferred(name, arguments)
+            }
+            _ => Self::parse_numeric(name, &numeric_arguments(name, arguments)?)?,
+        })
+    }
+
+    fn deferred(name: &str, arguments: &[PlausibleOperator<A>]) -> Self {
+        Self::Deferred {
+            name: name.to_string(),
+            arguments: arguments.into(),
+        }
+    }
+
+    fn parse_numeric(name: &str, values: &[isize]) -> Result<Self, Box<dyn Error>> {
+        Ok(match (name, values) {
+            (".align", [exponent, fill @ ..]) => Self::Align {
+                boundary: u32::try_from(*exponent)
+                    .ok()
+                    .and_then(|exponent| 1usize.checked_shl(exponent))
+                    .ok_or_else(|| format!("Invalid alignment: 2^{exponent}"))?,
+                fill: to_fill(fill)?,
+            },
+            (".balign", [boundary, fill @ ..]) => {
+                let boundary = to_address(*boundary)?;
+                if !boundary.is_power_of_two() {
+                    return Err(
+                        format!("Invalid alignment: {boundary} is not a power of two").into(),
+                    );
+                }
+                Self::Align {
+                    boundary,
+                    fill: to_fill(fill)?,
+                }
+            }
+            (".org", [address, fill @ ..]) => Self::Org {
+                address: to_address(*address)?,
+                fill: to_fill(fill)?,
+            },
+            _ => return Err(format!("Invalid directive: {name} {values:?}").into()),
+        })
+    }
+
+    /// Returns the number of bytes the directive occupies when found at `location`,
+    /// counted in bytes. Addresses and alignments count in [`Architecture::address_unit`].
+    pub fn size(&self, location: usize) -> usize {
+        match self {
+            Self::Align { .. } | Self::Org { .. } => self
+                .end(location)
+                .map_or(0, |end| end.saturating_sub(location)),
+            Self::Fill { count, .. } => *count,
+            Self::Section { .. }
+            | Self::Deferred { .. }
+            | Self::Equ { .. }
+            | Self::Scope { .. }
+            | Self::EndScope
+            | Self::Report { .. } => 0,
+            Self::Data { width, values } => width * values.len(),
+        }
+    }
+
+    /// Lowers the directive to the raw padding it produces when found at `location`.
+    pub fn lay_out(&self, location: usize) -> Result<Self, Box<dyn Error>> {
+        let end = self.end(location)?;
+        if let Self::Org { address, .. } = self
+            && end < location
+        {
+            return Err(format!(
+                "Can't move the location counter backwards (from {:#x} to {address:#x})",
+                location / A::address_unit()
+            )
+            .into());
+        }
+
+        let count = self.size(location);
+        Ok(match self {
+            Self::Align { fill, .. } | Self::Org { fill, .. } => Self::Fill {
+                count,
+                value: *fill,
+            },
+            directive => directive.clone(),
+        })
+    }
+
+    /// Returns the location, in bytes, that `.align` or `.org` pads up to
+    /// when found at `location`.
+    fn end(&self, location: usize) -> Result<usize, Box<dyn Error>> {
+        let unit = A::address_unit();
+        match self {
+            Self::Align { boundary, .. } => boundary
+                .checked_mul(unit)
+                .and_then(|boundary| location.checked_next_multiple_of(boundary))
+                .ok_or_else(|| format!("Alignment {boundary:#x} is out of range").into()),
+            Self::Org { address, .. } => address
+                .checked_mul(unit)
+                .ok_or_else(|| format!("Address {address:#x} is out of range").into()),
+            _ => Ok(location),
+        }
+    }
+}
+
+impl<A: Architecture> Debug for Directive<A>
+where
+    A::Symbol: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Align { boundary, fill } => write!(f, "Align({boundary}, {fill})"),
+            Self::Org { address, fill } => write!(f, "Org({address:#x}, {fill})"),
+            Self::Fill { count, value } => write!(f, "Fill({count}, {value})"),
+            Self::Deferred { name, arguments } => write!(f, "Deferred({name:?}, {arguments:?})"),
+            Self::Section { name, flags, base } => {
+                write!(f, "Section({name:?}, {flags:?}, {base:?})")
+            }
+            Self::Equ { name, value } => write!(f, "Equ({name:?}, {value:?})"),
+            Self::Data { width, values } => write!(f, "Data({width}, {values:?})"),
+            Self::Scope { name } => write!(f, "Scope({name:?})"),
+            Self::EndScope => write!(f, "EndScope"),
+            Self::Report {
+                level,
+                condition,
+                message,
+            } => write!(f, "Report({level:?}, {condition:?}, {message:?})"),
+        }
+    }
+}
+
+/// Returns the width in bytes of the values of the data directive `name`.
+pub(crate) fn data_width<A: Architecture>(name: &str) -> Option<usize> {
+    match name {
+        ".byte" => Some(1),
+        ".word" => Some(A::word_size()),
+        _ => None,
+    }
+}
+
+/// Whether `operator` stands for a value, possibly only known after layout.
+fn is_value<A: Architecture>(operator: &PlausibleOperator<A>) -> bool {
+    matches!(
+        operator,
+        PlausibleOperator::Value(_)
+            | PlausibleOperator::Identifier(_)
+            | PlausibleOperator::Expression(_)
+    )
+}
+
+fn parse_message<A: Architecture>(
+    name: &str,
+    message: &[PlausibleOperator<A>],
+) -> Result<Rc<[PlausibleOperator<A>]>, Box<dyn Error>> {
+    if !message
+        .iter()
+        .all(|part| matches!(part, PlausibleOperator::String(_)) || is_value(part))
+    {
+        return Err(format!("Directive {name} only accepts strings and values").into());
+    }
+    Ok(message.into())
+}
+
+fn numeric_arguments<A: Architecture>(
+    name: &str,
+    arguments: &[PlausibleOperator<A>],
+) -> Result<Vec<isize>, Box<dyn Error>> {
+    arguments
+        .iter()
+        .map(|argument| match argument {
+            PlausibleOperator::Value(value) => Ok(*value),
+            _ => Err(format!("Directive {name} only accepts numeric arguments").into()),
+        })
+        .collect()
+}
+
+fn to_address(value: isize) -> Result<usize, Box<dyn Error>> {
+    value
+        .try_into()
+        .map_err(|_| format!("Invalid address: {value}").into())
+}
+
+fn to_fill(fill: &[isize]) -> Result<u8, Box<dyn Error>> {
+    match fill {
+        [] => Ok(0),
+        [value] => (*value)
+            .try_into()
+            .map_err(|_| format!("Invalid fill byte: {value}").into()),
+        _ => Err("Too many arguments".into()),
+    }
+}
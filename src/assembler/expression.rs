@@ -0,0 +1,261 @@
+use crate::assembler::passes::tokenize::{Operator, Token};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+/// A constant expression, as found in operands and directive arguments.
+///
+/// Follows the C operator precedence. Comparisons and logical operators
+/// evaluate to `1` when true and `0` when false.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Expression {
+    Value(isize),
+    Identifier(String),
+    Unary(Operator, Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    pub fn parse(tokens: &[Token]) -> Result<Self, Box<dyn Error>> {
+        let mut parser = ExpressionParser {
+            tokens,
+            position: 0,
+        };
+        let expression = parser.parse_binary(0)?;
+
+        match parser.tokens.get(parser.position) {
+            None => Ok(expression),
+            Some(token) => Err(format!("Unexpected token in expression: {token:?}").into()),
+        }
+    }
+
+    /// Evaluates the expression, looking up the value of identifiers in `symbols`.
+    pub fn evaluate(
+        &self,
+        symbols: &impl Fn(&str) -> Option<isize>,
+    ) -> Result<isize, Box<dyn Error>> {
+        self.try_evaluate(&|identifier| {
+            symbols(identifier).ok_or_else(|| format!("Undefined symbol: {identifier}").into())
+        })
+    }
+
+    /// Evaluates the expression, looking up the value of identifiers with
+    /// `symbols`, which gives the reason when it can't.
+    pub fn try_evaluate(
+        &self,
+        symbols: &impl Fn(&str) -> Result<isize, Box<dyn Error>>,
+    ) -> Result<isize, Box<dyn Error>> {
+        match self {
+            Self::Value(value) => Ok(*value),
+            Self::Identifier(identifier) => symbols(identifier),
+            Self::Unary(operator, operand) => {
+                let operand = operand.try_evaluate(symbols)?;
+                Ok(match operator {
+                    Operator::Plus => operand,
+                    Operator::Minus => operand.checked_neg().ok_or("Overflow")?,
+                    Operator::BitNot => !operand,
+                    Operator::Not => (operand == 0) as isize,
+                    _ => unreachable!("{operator:?} isn't an unary operator"),
+                })
+            }
+            Self::Binary(operator, left, right) => {
+                let left = left.try_evaluate(symbols)?;
+                let right = right.try_evaluate(symbols)?;
+                Ok(match operator {
+                    Operator::Plus => left.checked_add(right).ok_or("Overflow")?,
+                    Operator::Minus => left.checked_sub(right).ok_or("Overflow")?,
+                    Operator::Multiply => left.checked_mul(right).ok_or("Overflow")?,
+                    Operator::Divide if right == 0 => return Err("Division by zero".into()),
+                    Operator::Divide => left.checked_div(right).ok_or("Overflow")?,
+                    Operator::Remainder if right == 0 => return Err("Division by zero".into()),
+                    Operator::Remainder => left.checked_rem(right).ok_or("Overflow")?,
+                    Operator::BitAnd => left & right,
+                    Operator::BitOr => left | right,
+                    Operator::BitXor => left ^ right,
+                    Operator::ShiftLeft => {
+                        let shifted = left.checked_shl(shift(right)?).ok_or("Overflow")?;
+                        // Bits shifted out, including the sign, are lost
+                        if shifted >> shift(right)? != left {
+                            return Err("Overflow".into());
+                        }
+                        shifted
+                    }
+                    Operator::ShiftRight => left.checked_shr(shift(right)?).ok_or("Overflow")?,
+                    Operator::And => (left != 0 && right != 0) as isize,
+                    Operator::Or => (left != 0 || right != 0) as isize,
+                    Operator::Equal => (left == right) as isize,
+                    Operator::NotEqual => (left != right) as isize,
+                    Operator::Less => (left < right) as isize,
+                    Operator::LessEqual => (left <= right) as isize,
+                    Operator::Greater => (left > right) as isize,
+                    Operator::GreaterEqual => (left >= right) as isize,
+                    _ => unreachable!("{operator:?} isn't a binary operator"),
+                })
+            }
+        }
+    }
+
+    /// Evaluates the expression if it doesn't reference any symbol.
+    pub fn evaluate_constant(&self) -> Option<isize> {
+        self.evaluate(&|_| None).ok()
+    }
+
+    /// Replaces the references to `identifier` with `value`.
+    pub fn replace(&self, identifier: &str, value: &Expression) -> Self {
+        self.map_identifiers(&|name| {
+            if name == identifier {
+                value.clone()
+            } else {
+                Self::Identifier(name.to_string())
+            }
+        })
+    }
+
+    /// Replaces each identifier with the result of `f`.
+    pub fn map_identifiers(&self, f: &impl Fn(&str) -> Expression) -> Self {
+        match self {
+            Self::Value(_) => self.clone(),
+            Self::Identifier(name) => f(name),
+            Self::Unary(operator, operand) => {
+                Self::Unary(*operator, Box::new(operand.map_identifiers(f)))
+            }
+            Self::Binary(operator, left, right) => Self::Binary(
+                *operator,
+                Box::new(left.map_identifiers(f)),
+                Box::new(right.map_identifiers(f)),
+            ),
+        }
+    }
+}
+
+impl Debug for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Value(value) => write!(f, "{value}"),
+            Self::Identifier(identifier) => write!(f, "{identifier}"),
+            Self::Unary(operator, operand) => write!(f, "{operator}{operand}"),
+            Self::Binary(operator, left, right) => write!(f, "({left} {operator} {right})"),
+        }
+    }
+}
+
+struct ExpressionParser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl ExpressionParser<'_> {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn peek_binary_operator(&self) -> Option<Operator> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(operator)) if precedence(*operator).is_some() => Some(*operator),
+            _ => None,
+        }
+    }
+
+    /// Parses a chain of binary operators binding at least as tight as `min_precedence`.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expression, Box<dyn Error>> {
+        let mut left = self.parse_unary()?;
+
+        while let Some(operator) = self.peek_binary_operator() {
+            let operator_precedence = precedence(operator).unwrap();
+            if operator_precedence < min_precedence {
+                break;
+            }
+
+            self.position += 1;
+            let right = self.parse_binary(operator_precedence + 1)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, Box<dyn Error>> {
+        match self.next() {
+            Some(Token::Value(value)) => Ok(Expression::Value(*value)),
+            Some(Token::Symbol(identifier)) => Ok(Expression::Identifier(identifier.clone())),
+            Some(Token::Operator(
+                operator @ (Operator::Plus | Operator::Minus | Operator::BitNot | Operator::Not),
+            )) => {
+                let operator = *operator;
+                Ok(Expression::Unary(operator, Box::new(self.parse_unary()?)))
+            }
+            Some(Token::Operator(Operator::OpenParenthesis)) => {
+                let expression = self.parse_binary(0)?;
+                match self.next() {
+                    Some(Token::Operator(Operator::CloseParenthesis)) => Ok(expression),
+                    _ => Err("Expected a closing parenthesis".into()),
+                }
+            }
+            Some(token) => Err(format!("Unexpected token in expression: {token:?}").into()),
+            None => Err("Expected an expression".into()),
+        }
+    }
+}
+
+/// The number of bits to shift by, which can't be negative.
+fn shift(count: isize) -> Result<u32, Box<dyn Error>> {
+    u32::try_from(count).map_err(|_| format!("Negative shift count: {count}").into())
+}
+
+fn precedence(operator: Operator) -> Option<u8> {
+    Some(match operator {
+        Operator::Or => 1,
+        Operator::And => 2,
+        Operator::BitOr => 3,
+        Operator::BitXor => 4,
+        Operator::BitAnd => 5,
+        Operator::Equal | Operator::NotEqual => 6,
+        Operator::Less | Operator::LessEqual | Operator::Greater | Operator::GreaterEqual => 7,
+        Operator::ShiftLeft | Operator::ShiftRight => 8,
+        Operator::Plus | Operator::Minus => 9,
+        Operator::Multiply | Operator::Divide | Operator::Remainder => 10,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::AssemblerPass;
+    use crate::assembler::passes::tokenize::TokenizePass;
+
+    fn evaluate(source: &str) -> Result<isize, String> {
+        let tokens = TokenizePass::default()
+            .apply_all(source.chars())
+            .into_iter()
+            .filter(|token| !matches!(token, Token::Location(_) | Token::LineFeed))
+            .collect::<Vec<_>>();
+        let expression = Expression::parse(&tokens).map_err(|error| error.to_string())?;
+        expression
+            .evaluate(&|_| None)
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn follows_c_precedence() {
+        assert_eq!(evaluate("1 + 2 * 3 << 1"), Ok(14));
+        assert_eq!(evaluate("-(4 - 6) == 2 && !0"), Ok(1));
+    }
+
+    #[test]
+    fn reports_overflow() {
+        assert_eq!(evaluate("0x7fffffffffffffff * 4"), Err("Overflow".into()));
+        assert_eq!(evaluate("1 << 64"), Err("Overflow".into()));
+        assert_eq!(evaluate("3 << 62"), Err("Overflow".into()));
+        assert_eq!(evaluate("1 << 62"), Ok(1 << 62));
+        assert_eq!(evaluate("1 >> -1"), Err("Negative shift count: -1".into()));
+        assert_eq!(evaluate("1 / 0"), Err("Division by zero".into()));
+    }
+}
@@ -1,11 +1,27 @@
-use crate::arch_def::Architecture;
+use crate::arch_def::{Architecture, CaseSensitivity, Extensions};
+use crate::assembler::passes::aliases::AliasPass;
+use crate::assembler::passes::conditionals::ConditionalPass;
 use crate::assembler::passes::emit::EmitPass;
+use crate::assembler::passes::include::IncludePass;
+use crate::assembler::passes::layout::LayoutPass;
+use crate::assembler::passes::local_labels::LocalLabelPass;
+use crate::assembler::passes::macros::MacroPass;
 use crate::assembler::passes::parse::ParsePass;
 use crate::assembler::passes::parse_operands::ParseOperandsPass;
+use crate::assembler::passes::pseudo_instructions::PseudoInstructionPass;
+use crate::assembler::passes::relaxation::RelaxationPass;
 use crate::assembler::passes::retokenize::RetokenizePass;
+use crate::assembler::passes::scopes::ScopePass;
+use crate::assembler::passes::structs::StructPass;
+use crate::assembler::passes::validate::ValidatePass;
 use passes::tokenize::TokenizePass;
+use std::path::{Path, PathBuf};
 
+pub mod diagnostics;
+pub mod directives;
+pub mod expression;
 pub mod passes;
+pub mod sections;
 
 pub trait AssemblerPass {
     type Input;
@@ -42,9 +58,20 @@ pub trait AssemblerPass {
 
 pub struct AssemblerPasses<A: Architecture> {
     tokenize: TokenizePass,
+    includes: IncludePass,
+    macros: MacroPass,
+    expanded_conditionals: ConditionalPass,
+    structs: StructPass<A>,
+    local_labels: LocalLabelPass,
+    aliases: AliasPass<A>,
     retokenize: RetokenizePass<A>,
     parse: ParsePass<A>,
+    scopes: ScopePass<A>,
+    pseudo_instructions: PseudoInstructionPass<A>,
+    relaxation: Option<RelaxationPass<A>>,
+    layout: LayoutPass<A>,
     parse_operands: ParseOperandsPass<A>,
+    validate: ValidatePass<A>,
     emit: EmitPass<A>,
 }
 
@@ -52,33 +79,128 @@ impl<A: Architecture> Default for AssemblerPasses<A> {
     fn default() -> Self {
         Self {
             tokenize: TokenizePass::default(),
+            includes: IncludePass::default().with_case_sensitivity(A::case_sensitivity()),
+            macros: MacroPass::default(),
+            expanded_conditionals: ConditionalPass::default()
+                .with_case_sensitivity(A::case_sensitivity()),
+            structs: StructPass::default(),
+            local_labels: LocalLabelPass::default(),
+            aliases: AliasPass::default(),
             retokenize: RetokenizePass::default(),
             parse: ParsePass::default(),
+            scopes: ScopePass::default(),
+            pseudo_instructions: PseudoInstructionPass::default(),
+            relaxation: None,
+            layout: LayoutPass::default(),
             parse_operands: ParseOperandsPass::default(),
+            validate: ValidatePass::default(),
             emit: EmitPass::default(),
         }
     }
 }
 
+impl<A: Architecture> AssemblerPasses<A> {
+    /// Names the file being assembled, for diagnostics and to resolve the
+    /// files it includes relative to it.
+    pub fn with_file(mut self, file: &Path) -> Self {
+        self.tokenize = TokenizePass::new(file.to_string_lossy().into());
+        self.includes = self.includes.with_file(file);
+        self
+    }
+
+    /// Enables branch relaxation, see [`RelaxationPass`]. Relaxed instructions
+    /// may overwrite the scratch register of the architecture, see
+    /// [`Architecture::relax`].
+    pub fn with_relaxation(mut self) -> Self {
+        self.relaxation = Some(RelaxationPass::default());
+        self
+    }
+
+    /// Enables branch relaxation, overwriting `scratch` instead of the scratch
+    /// register of the architecture.
+    pub fn with_relaxation_scratch(mut self, scratch: A::Symbol) -> Self {
+        self.relaxation = Some(RelaxationPass::default().with_scratch(scratch));
+        self
+    }
+
+    /// Overrides which names are matched regardless of case, see [`CaseSensitivity`].
+    pub fn with_case_sensitivity(mut self, case_sensitivity: CaseSensitivity) -> Self {
+        self.includes = self.includes.with_case_sensitivity(case_sensitivity);
+        self.expanded_conditionals =
+            ConditionalPass::default().with_case_sensitivity(case_sensitivity);
+        self.aliases = AliasPass::default().with_case_sensitivity(case_sensitivity);
+        self.retokenize = RetokenizePass::default().with_case_sensitivity(case_sensitivity);
+        self
+    }
+
+    /// Only accepts the instructions of the given extensions, see [`Extensions`].
+    pub fn with_extensions(mut self, extensions: Extensions) -> Self {
+        self.parse = ParsePass::default().with_extensions(extensions.clone());
+        self.parse_operands = ParseOperandsPass::default().with_extensions(extensions);
+        self
+    }
+
+    /// Sets the directories where included files are looked up.
+    pub fn with_include_paths(mut self, include_paths: Vec<PathBuf>) -> Self {
+        self.includes = self.includes.with_include_paths(include_paths);
+        self
+    }
+}
+
 impl<A: Architecture> AssemblerPass for AssemblerPasses<A> {
     type Input = <TokenizePass as AssemblerPass>::Input;
     type Output = <EmitPass<A> as AssemblerPass>::Output;
 
     fn apply(&mut self, item: Self::Input) -> impl IntoIterator<Item = Self::Output> {
         let tokens = self.tokenize.apply(item);
+        let tokens = self.includes.apply_all_partial(tokens);
+        let tokens = self.macros.apply_all_partial(tokens);
+        let tokens = self.expanded_conditionals.apply_all_partial(tokens);
+        let tokens = self.structs.apply_all_partial(tokens);
+        let tokens = self.local_labels.apply_all_partial(tokens);
+        let tokens = self.aliases.apply_all_partial(tokens);
         let tokens = self.retokenize.apply_all_partial(tokens);
         let ast_nodes = self.parse.apply_all_partial(tokens);
+        let ast_nodes = self.scopes.apply_all_partial(ast_nodes);
+        let ast_nodes = self.pseudo_instructions.apply_all_partial(ast_nodes);
+        let ast_nodes = match &mut self.relaxation {
+            Some(relaxation) => Vec::from_iter(relaxation.apply_all_partial(ast_nodes)),
+            None => Vec::from_iter(ast_nodes),
+        };
+        let ast_nodes = self.layout.apply_all_partial(ast_nodes);
         let ast_nodes = self.parse_operands.apply_all_partial(ast_nodes);
-        let bytes = self.emit.apply_all_partial(ast_nodes);
-        bytes
+        let ast_nodes = self.validate.apply_all_partial(ast_nodes);
+        self.emit.apply_all_partial(ast_nodes)
     }
 
     fn finish(&mut self) -> impl IntoIterator<Item = Self::Output> {
         let tokens = self.tokenize.finish();
+        let tokens = self.includes.apply_all(tokens);
+        let tokens = self.macros.apply_all(tokens);
+        let tokens = self.expanded_conditionals.apply_all(tokens);
+        let tokens = self.structs.apply_all(tokens);
+        let tokens = self.local_labels.apply_all(tokens);
+        let tokens = self.aliases.apply_all(tokens);
         let tokens = self.retokenize.apply_all(tokens);
         let ast_nodes = self.parse.apply_all(tokens);
+        let ast_nodes = self.scopes.apply_all(ast_nodes);
+        let ast_nodes = self.pseudo_instructions.apply_all(ast_nodes);
+        let ast_nodes = match &mut self.relaxation {
+            Some(relaxation) => Vec::from_iter(relaxation.apply_all(ast_nodes)),
+            None => Vec::from_iter(ast_nodes),
+        };
+        let ast_nodes = self.layout.apply_all(ast_nodes);
         let ast_nodes = self.parse_operands.apply_all(ast_nodes);
-        let bytes = self.emit.apply_all(ast_nodes);
-        bytes
+        let ast_nodes = self.validate.apply_all(ast_nodes);
+        self.emit.apply_all(ast_nodes)
     }
 }
+
+/// Assembles `source` with `passes`, returning the sections.
+#[cfg(test)]
+pub(crate) fn assemble<A: Architecture>(
+    mut passes: AssemblerPasses<A>,
+    source: &str,
+) -> Vec<sections::Section> {
+    passes.apply_all(source.chars()).into_iter().collect()
+}
@@ -0,0 +1,130 @@
+use crate::arch_def::{Architecture, CaseSensitivity, Symbol};
+use crate::assembler::diagnostics::{Location, error};
+use crate::assembler::passes::lines::LinePass;
+use crate::assembler::passes::tokenize::{Token, location, statement};
+use std::marker::PhantomData;
+
+/// Gives other names to symbols, like registers.
+///
+/// ```text
+/// .alias counter, r3
+///     addi counter, counter, -1
+/// ```
+///
+/// This assembles `addi r3, r3, -1`.
+///
+/// `.reg` is a synonym of `.alias`. Aliases apply from their definition to the
+/// end of the program, on top of those of the architecture, see
+/// [`Architecture::symbol_aliases`]. They can be redefined, and can be given
+/// another alias as their symbol. Runs after the macro pass, so macros can use
+/// the aliases defined outside of them.
+pub struct AliasPass<A: Architecture> {
+    line: Vec<Token>,
+    location: Location,
+    case_sensitivity: CaseSensitivity,
+    /// Each alias with the symbol it stands for, in order of definition
+    aliases: Vec<(String, String)>,
+    phantom_architecture: PhantomData<A>,
+}
+
+impl<A: Architecture> Default for AliasPass<A> {
+    fn default() -> Self {
+        Self {
+            line: vec![],
+            location: Location::default(),
+            case_sensitivity: A::case_sensitivity(),
+            aliases: A::symbol_aliases()
+                .iter()
+                .map(|(alias, symbol)| (alias.to_string(), symbol.to_string()))
+                .collect(),
+            phantom_architecture: PhantomData,
+        }
+    }
+}
+
+impl<A: Architecture> LinePass for AliasPass<A> {
+    fn line(&mut self) -> &mut Vec<Token> {
+        &mut self.line
+    }
+
+    fn process_line(&mut self, mut line: Vec<Token>) -> Vec<Token> {
+        if let Some(location) = location(&line) {
+            self.location = location.clone();
+        }
+
+        let start = line.len() - statement(&line).len();
+        match &line[start..] {
+            [
+                Token::Symbol(directive),
+                Token::Symbol(alias),
+                Token::Comma,
+                Token::Symbol(symbol),
+            ] if self.is_alias_directive(directive) => {
+                if self.is_symbol(alias) {
+                    error(
+                        &self.location,
+                        format!("Can't use {alias} as an alias, it's already a symbol"),
+                    );
+                }
+                let symbol = self.resolve(symbol).unwrap_or(symbol).to_string();
+                if !self.is_symbol(&symbol) {
+                    error(&self.location, format!("{symbol} isn't a symbol"));
+                }
+
+                self.aliases.push((alias.clone(), symbol));
+                line.truncate(start);
+                line
+            }
+            [Token::Symbol(directive), ..] if self.is_alias_directive(directive) => error(
+                &self.location,
+                format!("Directive {directive} expects an alias and a symbol"),
+            ),
+            _ => {
+                // Labels keep their name, only the statement is renamed
+                for token in &mut line[start..] {
+                    if let Token::Symbol(name) = token
+                        && let Some(symbol) = self.resolve(name)
+                    {
+                        *name = symbol.to_string();
+                    }
+                }
+                line
+            }
+        }
+    }
+}
+
+impl<A: Architecture> AliasPass<A> {
+    /// Overrides the case sensitivity of the architecture. Aliases ignore case
+    /// along with the symbols they stand for.
+    pub fn with_case_sensitivity(mut self, case_sensitivity: CaseSensitivity) -> Self {
+        self.case_sensitivity = case_sensitivity;
+        self
+    }
+
+    fn is_alias_directive(&self, directive: &str) -> bool {
+        [".alias", ".reg"]
+            .into_iter()
+            .any(|name| match self.case_sensitivity {
+                CaseSensitivity::Insensitive => name.eq_ignore_ascii_case(directive),
+                _ => name == directive,
+            })
+    }
+
+    /// Returns the symbol `name` stands for, if it's an alias.
+    fn resolve(&self, name: &str) -> Option<&str> {
+        let ignore_case = self.case_sensitivity != CaseSensitivity::Sensitive;
+        self.aliases
+            .iter()
+            .rev()
+            .find(|(alias, _)| alias == name || ignore_case && alias.eq_ignore_ascii_case(name))
+            .map(|(_, symbol)| symbol.as_str())
+    }
+
+    /// Whether `name` is a symbol of the architecture, as matched when retokenizing.
+    fn is_symbol(&self, name: &str) -> bool {
+        A::Symbol::parse(name).is_ok()
+            || self.case_sensitivity != CaseSensitivity::Sensitive
+                && A::Symbol::parse(&name.to_ascii_lowercase()).is_ok()
+    }
+}
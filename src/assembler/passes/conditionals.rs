@@ -0,0 +1,262 @@
+use crate::arch_def::CaseSensitivity;
+use crate::assembler::diagnostics::{Location, error};
+use crate::assembler::expression::Expression;
+use crate::assembler::passes::lines::LinePass;
+use crate::assembler::passes::tokenize::{Token, labels, location, statement};
+use std::collections::HashMap;
+
+/// Drops the lines inside disabled conditional blocks, so they don't even need
+/// to be valid instructions.
+///
+/// ```text
+/// .if expression / .ifdef symbol / .ifndef symbol
+///     ...
+/// .elseif expression
+///     ...
+/// .else
+///     ...
+/// .endif
+/// ```
+///
+/// Conditions are evaluated with the constants defined so far with `.equ`, and
+/// `.ifdef`/`.ifndef` also take into account the labels defined so far. Their
+/// names ignore case like the labels do, see [`CaseSensitivity::Insensitive`].
+///
+/// The contents of `.macro` definitions and repetitions are left untouched, as
+/// their conditions may depend on the macro parameters. A first instance runs
+/// within the [`IncludePass`](crate::assembler::passes::include::IncludePass),
+/// and another one after the macro pass to evaluate them once expanded.
+#[derive(Default)]
+pub struct ConditionalPass {
+    line: Vec<Token>,
+    blocks: Vec<ConditionalBlock>,
+    /// Defined symbols, with their value if it's known
+    symbols: HashMap<String, Option<isize>>,
+    /// How deep into macro definitions and repetitions the current line is
+    deferred_depth: usize,
+    case_sensitivity: CaseSensitivity,
+    location: Location,
+}
+
+struct ConditionalBlock {
+    /// Whether the lines in the current branch are assembled
+    active: bool,
+    /// Whether no further branch can be taken, either because one was already
+    /// taken or because the whole block is disabled
+    taken: bool,
+    in_else: bool,
+}
+
+impl LinePass for ConditionalPass {
+    fn line(&mut self) -> &mut Vec<Token> {
+        &mut self.line
+    }
+
+    fn process_line(&mut self, line: Vec<Token>) -> Vec<Token> {
+        if let Some(location) = location(&line) {
+            self.location = location.clone();
+        }
+
+        let directive = match statement(&line) {
+            [Token::Symbol(directive), ..] => directive.as_str(),
+            _ => "",
+        };
+
+        if self.deferred_depth > 0 {
+            match directive {
+                ".macro" | ".rept" | ".irp" | ".irpc" => self.deferred_depth += 1,
+                ".endm" | ".endr" => self.deferred_depth -= 1,
+                _ => {}
+            }
+            return line;
+        }
+
+        let arguments = match statement(&line) {
+            [_, arguments @ ..] => arguments,
+            [] => &[],
+        };
+
+        match directive {
+            ".if" | ".ifdef" | ".ifndef" => {
+                let condition = self.enabled() && self.evaluate(directive, arguments);
+                self.blocks.push(ConditionalBlock {
+                    active: condition,
+                    taken: condition || !self.enabled(),
+                    in_else: false,
+                });
+            }
+            ".elseif" => {
+                let taken = self.current_block(directive).taken;
+                let condition = !taken && self.evaluate(".if", arguments);
+                let block = self.current_block(directive);
+                block.active = condition;
+                block.taken |= condition;
+            }
+            ".else" => {
+                let block = self.current_block(directive);
+                block.active = !block.taken;
+                block.taken = true;
+                block.in_else = true;
+            }
+            ".endif" => {
+                self.blocks
+                    .pop()
+                    .unwrap_or_else(|| error(&self.location, "Unexpected .endif"));
+            }
+            _ if self.enabled() => {
+                self.record_definitions(&line);
+                if matches!(directive, ".macro" | ".rept" | ".irp" | ".irpc") {
+                    self.deferred_depth = 1;
+                }
+                return line;
+            }
+            _ => {}
+        }
+
+        vec![]
+    }
+
+    fn finish_lines(&mut self) {
+        if !self.blocks.is_empty() {
+            error(&self.location, "Missing .endif");
+        }
+    }
+}
+
+impl ConditionalPass {
+    pub fn with_case_sensitivity(mut self, case_sensitivity: CaseSensitivity) -> Self {
+        self.case_sensitivity = case_sensitivity;
+        self
+    }
+
+    /// The name `symbol` is recorded as.
+    fn fold(&self, symbol: &str) -> String {
+        match self.case_sensitivity {
+            CaseSensitivity::Insensitive => symbol.to_ascii_lowercase(),
+            _ => symbol.to_string(),
+        }
+    }
+
+    fn value(&self, symbol: &str) -> Option<isize> {
+        self.symbols.get(&self.fold(symbol)).copied().flatten()
+    }
+
+    fn enabled(&self) -> bool {
+        self.blocks.iter().all(|block| block.active)
+    }
+
+    fn current_block(&mut self, directive: &str) -> &mut ConditionalBlock {
+        match self.blocks.last_mut() {
+            Some(block) if !block.in_else => block,
+            _ => error(&self.location, format!("Unexpected {directive}")),
+        }
+    }
+
+    fn evaluate(&self, directive: &str, arguments: &[Token]) -> bool {
+        match (directive, arguments) {
+            (".ifdef", [Token::Symbol(symbol)]) => self.symbols.contains_key(&self.fold(symbol)),
+            (".ifndef", [Token::Symbol(symbol)]) => !self.symbols.contains_key(&self.fold(symbol)),
+            (".if", condition) => {
+                let value = Expression::parse(condition)
+                    .and_then(|condition| condition.evaluate(&|symbol| self.value(symbol)))
+                    .unwrap_or_else(|message| {
+                        error(
+                            &self.location,
+                            format!("Can't evaluate condition: {message}"),
+                        )
+                    });
+                value != 0
+            }
+            _ => error(&self.location, format!("Invalid arguments for {directive}")),
+        }
+    }
+
+    fn record_definitions(&mut self, line: &[Token]) {
+        for label in labels(line) {
+            self.symbols.insert(self.fold(label), None);
+        }
+
+        if let [
+            Token::Symbol(directive),
+            Token::Symbol(name),
+            Token::Comma,
+            value @ ..,
+        ] = statement(line)
+            && directive == ".equ"
+        {
+            let value = Expression::parse(value)
+                .ok()
+                .and_then(|value| value.evaluate(&|symbol| self.value(symbol)).ok());
+            self.symbols.insert(self.fold(name), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::AssemblerPass;
+    use crate::assembler::passes::tokenize::{normalize, tokenize};
+
+    fn evaluate(mut pass: ConditionalPass, source: &str) -> Vec<Token> {
+        normalize(pass.apply_all(tokenize(source)))
+    }
+
+    #[test]
+    fn keeps_the_taken_branch() {
+        let source = "
+            .equ N, 3
+            .if N > 5
+            .byte 1
+            .elseif N == 3
+            .byte 2
+            .else
+            .byte 3
+            .endif
+            .ifndef N
+            .byte 4
+            .endif
+        ";
+        let expected = tokenize(".equ N, 3\n.byte 2");
+        assert_eq!(evaluate(ConditionalPass::default(), source), expected);
+    }
+
+    #[test]
+    fn leaves_macros_and_repetitions_alone() {
+        let source = ".rept 2\n.if \\x\n.endif\n.endr";
+        assert_eq!(
+            evaluate(ConditionalPass::default(), source),
+            tokenize(source)
+        );
+    }
+
+    #[test]
+    fn ignores_the_case_of_symbols_if_insensitive() {
+        let source = "
+            .equ N, 3
+            label:
+            .ifdef n
+            .byte 1
+            .endif
+            .if n == 3
+            .byte 2
+            .endif
+            .ifdef LABEL
+            .byte 3
+            .endif
+        ";
+        let insensitive =
+            ConditionalPass::default().with_case_sensitivity(CaseSensitivity::Insensitive);
+        let expected = tokenize(".equ N, 3\nlabel:\n.byte 1\n.byte 2\n.byte 3");
+        assert_eq!(evaluate(insensitive, source), expected);
+    }
+
+    #[test]
+    fn matches_the_case_of_symbols_if_sensitive() {
+        let source = ".equ N, 3\n.ifdef n\n.byte 1\n.endif";
+        assert_eq!(
+            evaluate(ConditionalPass::default(), source),
+            tokenize(".equ N, 3")
+        );
+    }
+}
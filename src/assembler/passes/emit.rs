@@ -1,15 +1,28 @@
 use crate::arch_def::{Architecture, Instruction};
 use crate::assembler::AssemblerPass;
+use crate::assembler::diagnostics::{Location, error};
+use crate::assembler::directives::Directive;
+use crate::assembler::passes::parse::PlausibleOperator;
 use crate::assembler::passes::parse_operands::ASTNodeOperandsParsed;
+use crate::assembler::sections::Section;
+use std::error::Error;
 use std::marker::PhantomData;
 
+/// Writes each node into the section it was laid out in. The sections are
+/// emitted once finished, in order of appearance.
 pub struct EmitPass<A: Architecture> {
+    sections: Vec<Section>,
+    current_section: Option<usize>,
+    location: Location,
     phantom_architecture: PhantomData<A>,
 }
 
 impl<A: Architecture> Default for EmitPass<A> {
     fn default() -> Self {
         Self {
+            sections: vec![],
+            current_section: None,
+            location: Location::default(),
             phantom_architecture: PhantomData,
         }
     }
@@ -17,19 +30,77 @@ impl<A: Architecture> Default for EmitPass<A> {
 
 impl<A: Architecture> AssemblerPass for EmitPass<A> {
     type Input = ASTNodeOperandsParsed<A>;
-    type Output = u8;
+    type Output = Section;
 
     fn apply(&mut self, input: Self::Input) -> impl IntoIterator<Item = Self::Output> {
-        match input {
-            ASTNodeOperandsParsed::Instruction(inst, ops) => {
-                let mut bytes = vec![];
-
-                for byte in inst.emit(ops.iter().cloned()) {
-                    bytes.push(byte);
-                }
-
-                bytes
+        let result = match input {
+            ASTNodeOperandsParsed::Instruction(inst, ops) => self
+                .current_section()
+                .extend(inst.emit(ops.iter().cloned())),
+            ASTNodeOperandsParsed::Directive(Directive::Section {
+                name,
+                flags: Some(flags),
+                base: Some(base),
+            }) => {
+                let index = match self.sections.iter().position(|s| s.name == name) {
+                    Some(index) => index,
+                    None => {
+                        self.sections.push(Section::new(name, flags, base));
+                        self.sections.len() - 1
+                    }
+                };
+                self.current_section = Some(index);
+                Ok(())
+            }
+            ASTNodeOperandsParsed::Directive(Directive::Fill { count, value }) => {
+                self.current_section().extend_data(vec![value; count])
+            }
+            ASTNodeOperandsParsed::Directive(Directive::Data { width, values }) => values
+                .iter()
+                .map(|value| data_bytes::<A>(width, value))
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|bytes| self.current_section().extend_data(bytes.concat())),
+            ASTNodeOperandsParsed::Directive(_) => unreachable!("Directive wasn't laid out"),
+            ASTNodeOperandsParsed::Location(location) => {
+                self.location = location;
+                Ok(())
             }
+        };
+
+        if let Err(message) = result {
+            error(&self.location, message);
         }
+        vec![]
+    }
+
+    fn finish(&mut self) -> impl IntoIterator<Item = Self::Output> {
+        self.current_section = None;
+        self.sections.drain(..).collect::<Vec<_>>()
     }
 }
+
+impl<A: Architecture> EmitPass<A> {
+    fn current_section(&mut self) -> &mut Section {
+        let index = self
+            .current_section
+            .expect("Nodes must be laid out into a section");
+        &mut self.sections[index]
+    }
+}
+
+fn data_bytes<A: Architecture>(
+    width: usize,
+    value: &PlausibleOperator<A>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let PlausibleOperator::Value(value) = value else {
+        unreachable!("Data wasn't laid out")
+    };
+
+    let bytes = A::endianness().bytes(*value as u64, width)?;
+    let bits = width * 8;
+    if (*value as i128) < -(1i128 << (bits - 1)) || (*value as i128) >= (1i128 << bits) {
+        return Err(format!("Value {value} doesn't fit in {width} bytes").into());
+    }
+
+    Ok(bytes)
+}
@@ -0,0 +1,277 @@
+use crate::arch_def::CaseSensitivity;
+use crate::assembler::AssemblerPass;
+use crate::assembler::diagnostics::{Location, error};
+use crate::assembler::expression::Expression;
+use crate::assembler::passes::conditionals::ConditionalPass;
+use crate::assembler::passes::lines::LinePass;
+use crate::assembler::passes::tokenize::{Token, TokenizePass, labels, location, statement};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Splices other files into the source.
+///
+/// ```text
+/// .include "file.s"
+/// .incbin "file.bin"[, offset[, length]]
+/// ```
+///
+/// `.include` tokenizes the given file in place, so its lines keep their own
+/// location. `.incbin` inserts the raw bytes of the given file, as if they were
+/// listed with `.byte`. Paths are looked up relative to the directory of the
+/// including file first, then in each of the include paths.
+///
+/// Evaluates conditionals along the way, see [`ConditionalPass`], so only the
+/// files in enabled blocks are read, and included files can define what later
+/// conditions test.
+///
+/// When directives ignore case, see [`CaseSensitivity::Insensitive`], their
+/// names are lowercased first, so the passes after this one only match
+/// lowercase directives.
+#[derive(Default)]
+pub struct IncludePass {
+    include_paths: Vec<PathBuf>,
+    case_sensitivity: CaseSensitivity,
+    line: Vec<Token>,
+    conditionals: ConditionalPass,
+    location: Location,
+    /// The files being included, outermost first, to detect cycles
+    files: Vec<PathBuf>,
+}
+
+impl LinePass for IncludePass {
+    fn line(&mut self) -> &mut Vec<Token> {
+        &mut self.line
+    }
+
+    fn process_line(&mut self, mut line: Vec<Token>) -> Vec<Token> {
+        if self.case_sensitivity == CaseSensitivity::Insensitive {
+            let start = line.len() - statement(&line).len();
+            if let Some(Token::Symbol(directive)) = line.get_mut(start)
+                && directive.starts_with('.')
+            {
+                directive.make_ascii_lowercase();
+            }
+        }
+        let line = self.conditionals.process_line(line);
+        self.splice(line)
+    }
+
+    fn finish_lines(&mut self) {
+        self.conditionals.finish_lines();
+    }
+}
+
+impl IncludePass {
+    pub fn with_include_paths(mut self, include_paths: Vec<PathBuf>) -> Self {
+        self.include_paths = include_paths;
+        self
+    }
+
+    pub fn with_case_sensitivity(mut self, case_sensitivity: CaseSensitivity) -> Self {
+        self.case_sensitivity = case_sensitivity;
+        self.conditionals =
+            std::mem::take(&mut self.conditionals).with_case_sensitivity(case_sensitivity);
+        self
+    }
+
+    /// Sets the file being assembled, so that it can't be included by itself.
+    pub fn with_file(mut self, file: &Path) -> Self {
+        self.files.extend(fs::canonicalize(file).ok());
+        self
+    }
+
+    /// Replaces an `.include` or `.incbin` in an enabled line with the file.
+    fn splice(&mut self, line: Vec<Token>) -> Vec<Token> {
+        if let Some(location) = location(&line) {
+            self.location = location.clone();
+        }
+
+        let prefix = &line[..line.len() - statement(&line).len()];
+        let mut output = prefix.to_vec();
+        if !labels(&line).is_empty() {
+            output.push(Token::LineFeed);
+        }
+
+        match statement(&line) {
+            [Token::Symbol(directive), Token::String(path)] if directive == ".include" => {
+                let path = self
+                    .resolve(path)
+                    .unwrap_or_else(|message| error(&self.location, message));
+                output.extend(self.include(&path));
+                output
+            }
+            [
+                Token::Symbol(directive),
+                Token::String(path),
+                arguments @ ..,
+            ] if directive == ".incbin" => {
+                let bytes = self
+                    .resolve(path)
+                    .and_then(|path| read_binary(&path, arguments))
+                    .unwrap_or_else(|message| error(&self.location, message));
+
+                if !bytes.is_empty() {
+                    output.push(Token::Symbol(".byte".to_string()));
+                    for (i, byte) in bytes.into_iter().enumerate() {
+                        if i > 0 {
+                            output.push(Token::Comma);
+                        }
+                        output.push(Token::Value(byte as isize));
+                    }
+                }
+                output
+            }
+            [Token::Symbol(directive), ..] if directive == ".include" || directive == ".incbin" => {
+                error(
+                    &self.location,
+                    format!("Directive {directive} expects a file name"),
+                )
+            }
+            _ => line,
+        }
+    }
+
+    /// Looks `path` up next to the including file, then in the include paths.
+    fn resolve(&self, path: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let directory = Path::new(&*self.location.file)
+            .parent()
+            .unwrap_or(Path::new(""));
+
+        std::iter::once(directory)
+            .chain(self.include_paths.iter().map(PathBuf::as_path))
+            .map(|directory| directory.join(path))
+            .find(|path| path.is_file())
+            .ok_or_else(|| format!("Can't find file {path}").into())
+    }
+
+    fn include(&mut self, path: &Path) -> Vec<Token> {
+        let contents = fs::read_to_string(path).unwrap_or_else(|message| {
+            error(
+                &self.location,
+                format!("Can't read {}: {message}", path.display()),
+            )
+        });
+
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if self.files.contains(&canonical) {
+            let cycle = self
+                .files
+                .iter()
+                .skip_while(|file| **file != canonical)
+                .chain([&canonical])
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>();
+            error(
+                &self.location,
+                format!("Include cycle: {}", cycle.join(" -> ")),
+            );
+        }
+
+        self.files.push(canonical);
+        let including_location = self.location.clone();
+
+        let mut tokenize = TokenizePass::new(path.to_string_lossy().into());
+        let tokens = Vec::from_iter(tokenize.apply_all(contents.chars()));
+        let mut output = vec![];
+        for line in tokens.split(|token| *token == Token::LineFeed) {
+            if !line.is_empty() {
+                output.extend(self.process_line(line.to_vec()));
+                output.push(Token::LineFeed);
+            }
+        }
+
+        self.location = including_location;
+        self.files.pop();
+        output
+    }
+}
+
+/// Reads `length` bytes of the file at `path` from `offset`, both optional.
+fn read_binary(path: &Path, arguments: &[Token]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let arguments = match arguments {
+        [] => vec![],
+        [Token::Comma, arguments @ ..] => arguments
+            .split(|token| *token == Token::Comma)
+            .map(|argument| {
+                Expression::parse(argument)?
+                    .evaluate_constant()
+                    .and_then(|value| usize::try_from(value).ok())
+                    .ok_or_else(|| "Expected a constant, positive value".into())
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?,
+        _ => return Err("Invalid arguments for .incbin".into()),
+    };
+
+    let bytes =
+        fs::read(path).map_err(|message| format!("Can't read {}: {message}", path.display()))?;
+    let (offset, length) = match arguments[..] {
+        [] => (0, None),
+        [offset] => (offset, None),
+        [offset, length] => (offset, Some(length)),
+        _ => return Err("Too many arguments for .incbin".into()),
+    };
+
+    let end = length.map_or(Some(bytes.len()), |length| offset.checked_add(length));
+    bytes
+        .get(offset..end.unwrap_or(usize::MAX))
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| format!("{} is only {} bytes long", path.display(), bytes.len()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::passes::tokenize::{normalize, tokenize};
+    use std::{env, process};
+
+    /// Creates a directory named after the test with `files` in it.
+    fn directory(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let directory = env::temp_dir().join(format!("nara-include-{}-{name}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for (file, contents) in files {
+            fs::write(directory.join(file), contents).unwrap();
+        }
+        directory
+    }
+
+    fn include(directory: PathBuf, source: &str) -> Vec<Token> {
+        let mut pass = IncludePass::default().with_include_paths(vec![directory]);
+        normalize(pass.apply_all(tokenize(source)))
+    }
+
+    #[test]
+    fn splices_files() {
+        let directory = directory(
+            "splices",
+            &[
+                ("outer.s", b".byte 1\n.include \"inner.s\"\n"),
+                ("inner.s", b".byte 2\n"),
+                ("data.bin", &[10, 20, 30, 40]),
+            ],
+        );
+        let source = ".include \"outer.s\"\n.incbin \"data.bin\"\n.incbin \"data.bin\", 1, 2";
+        let expected = tokenize(".byte 1\n.byte 2\n.byte 10, 20, 30, 40\n.byte 20, 30");
+        assert_eq!(include(directory, source), expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "data.bin is only 4 bytes long")]
+    fn rejects_binaries_too_short() {
+        let directory = directory("short", &[("data.bin", &[10, 20, 30, 40])]);
+        include(directory, ".incbin \"data.bin\", 2, 3");
+    }
+
+    #[test]
+    #[should_panic(expected = "Include cycle")]
+    fn rejects_cycles() {
+        let directory = directory(
+            "cycle",
+            &[
+                ("first.s", b".include \"second.s\"\n"),
+                ("second.s", b".include \"first.s\"\n"),
+            ],
+        );
+        include(directory, ".include \"first.s\"");
+    }
+}
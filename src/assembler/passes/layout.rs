@@ -0,0 +1,519 @@
+use crate::arch_def::{Architecture, Instruction};
+use crate::assembler::AssemblerPass;
+use crate::assembler::diagnostics::{Location, error, report};
+use crate::assembler::directives::Directive;
+use crate::assembler::expression::Expression;
+use crate::assembler::passes::parse::{ASTNode, PlausibleOperator};
+use crate::assembler::passes::parse_operands::parse_operands;
+use crate::assembler::sections::{Section, SectionFlags};
+use std::collections::HashMap;
+use std::error::Error;
+use std::rc::Rc;
+
+/// Assigns an address to every node, keeping track of the location counter of
+/// each section, and replaces references to labels and constants with their value.
+///
+/// Labels may be referenced before being defined, so the whole program is
+/// buffered and only emitted once finished. So may constants, whose value is
+/// only evaluated when needed, like `.equ LEN, end - start` before `end:`.
+/// The `.` symbol stands for the address of the statement it appears in.
+///
+/// Instructions whose size depends on the value of their operands are first
+/// laid out with the size guessed by [`Instruction::size`], and the program is
+/// laid out again with their actual size until all of them settle.
+pub struct LayoutPass<A: Architecture> {
+    sections: Vec<SectionLayout>,
+    current_section: Option<usize>,
+    /// The address of each label, and the value of each constant
+    symbols: HashMap<String, Expression>,
+    nodes: Vec<ASTNode<A>>,
+    location: Location,
+    /// The nodes as received, to lay them out again
+    inputs: Vec<ASTNode<A>>,
+    /// The size each instruction was laid out with, in order
+    sizes: Vec<usize>,
+    /// The sizes found by the previous layout, used instead of guessing
+    previous_sizes: Vec<usize>,
+}
+
+/// How many times the program is laid out before giving up on instruction
+/// sizes that keep changing.
+const MAX_LAYOUTS: usize = 16;
+
+struct SectionLayout {
+    name: String,
+    flags: SectionFlags,
+    /// The address the section starts at
+    base: usize,
+    /// The location counter, in bytes
+    location: usize,
+}
+
+impl<A: Architecture> Default for LayoutPass<A> {
+    fn default() -> Self {
+        Self {
+            sections: vec![],
+            current_section: None,
+            symbols: HashMap::new(),
+            nodes: vec![],
+            location: Location::default(),
+            inputs: vec![],
+            sizes: vec![],
+            previous_sizes: vec![],
+        }
+    }
+}
+
+impl<A: Architecture> AssemblerPass for LayoutPass<A> {
+    type Input = ASTNode<A>;
+    type Output = ASTNode<A>;
+
+    fn apply(&mut self, item: Self::Input) -> impl IntoIterator<Item = Self::Output> {
+        self.inputs.push(item.clone());
+        self.lay_out(item);
+        vec![]
+    }
+
+    fn finish(&mut self) -> impl IntoIterator<Item = Self::Output> {
+        let mut layouts = 1;
+        loop {
+            let sizes = self.resolved_sizes();
+            if sizes == self.sizes {
+                break;
+            }
+            if layouts == MAX_LAYOUTS {
+                error(&self.location, "Instruction sizes don't settle");
+            }
+            layouts += 1;
+
+            let mut layout = Self {
+                previous_sizes: sizes,
+                ..Self::default()
+            };
+            let _ = Vec::from_iter(layout.apply_all_partial(std::mem::take(&mut self.inputs)));
+            *self = layout;
+        }
+
+        let mut location = Location::default();
+        let resolve = |operators: &[PlausibleOperator<A>], location: &Location| {
+            resolve_all(&self.symbols, operators).unwrap_or_else(|message| error(location, message))
+        };
+
+        self.nodes
+            .drain(..)
+            .filter_map(|node| {
+                Some(match node {
+                    ASTNode::Instruction(inst, ops) => {
+                        ASTNode::Instruction(inst, resolve(&ops, &location))
+                    }
+                    ASTNode::Directive(Directive::Data { width, values }) => {
+                        ASTNode::Directive(Directive::Data {
+                            width,
+                            values: resolve(&values, &location),
+                        })
+                    }
+                    ASTNode::Directive(Directive::Report {
+                        level,
+                        condition,
+                        message,
+                    }) => {
+                        let failed = condition.is_none_or(|condition| {
+                            matches!(
+                                *resolve(&[condition], &location),
+                                [PlausibleOperator::Value(0)]
+                            )
+                        });
+                        if failed {
+                            report(level, &location, describe(&resolve(&message, &location)));
+                        }
+                        return None;
+                    }
+                    ASTNode::Location(node_location) => {
+                        location = node_location.clone();
+                        ASTNode::Location(node_location)
+                    }
+                    node => node,
+                })
+            })
+            .collect::<Vec<_>>()
+    }
+}
+
+impl<A: Architecture> LayoutPass<A> {
+    /// Lays out a node at the current location.
+    fn lay_out(&mut self, item: ASTNode<A>) {
+        match item {
+            ASTNode::Label(label) => {
+                let address = self.address().unwrap_or_else(|| {
+                    error(
+                        &self.location,
+                        format!("Label {label} is in the middle of an addressable unit"),
+                    )
+                });
+                self.define(label, Expression::Value(address));
+            }
+            ASTNode::Directive(Directive::Equ { name, value }) => {
+                let value = self.here(&[value])[0]
+                    .to_expression()
+                    .unwrap_or_else(|| error(&self.location, "Expected a value"));
+                self.define(name, value);
+            }
+            ASTNode::Instruction(inst, ops) => {
+                let ops = self.here(&ops);
+                let size = match self.previous_sizes.get(self.sizes.len()) {
+                    Some(size) => *size,
+                    None => instruction_size(inst, &ops)
+                        .unwrap_or_else(|message| error(&self.location, message)),
+                };
+                self.sizes.push(size);
+                self.current_section().location += size;
+                self.nodes.push(ASTNode::Instruction(inst, ops));
+            }
+            ASTNode::Directive(Directive::Deferred { name, arguments }) => {
+                // The section name is an identifier, but not a symbol
+                let (kept, values) = arguments.split_at(usize::from(name == ".section"));
+                let values = self.here(values);
+                let arguments = resolve_all(&self.symbols, &values)
+                    .map(|values| [kept, &values].concat())
+                    .unwrap_or_else(|message| error(&self.location, message));
+                let directive = Directive::parse(&name, &arguments)
+                    .unwrap_or_else(|message| error(&self.location, message));
+                self.lay_out(ASTNode::Directive(directive));
+            }
+            ASTNode::Directive(Directive::Section { name, flags, base }) => {
+                self.switch_section(name, flags, base);
+            }
+            ASTNode::Directive(directive) => {
+                let directive = match directive {
+                    Directive::Data { width, values } => Directive::Data {
+                        width,
+                        values: self.here(&values),
+                    },
+                    Directive::Report {
+                        level,
+                        condition,
+                        message,
+                    } => Directive::Report {
+                        level,
+                        condition: condition.map(|condition| self.here(&[condition])[0].clone()),
+                        message: self.here(&message),
+                    },
+                    directive => directive,
+                };
+                let location = self.current_section().location;
+                let directive = directive
+                    .lay_out(location)
+                    .unwrap_or_else(|message| error(&self.location, message));
+                self.current_section().location += directive.size(location);
+                self.nodes.push(ASTNode::Directive(directive));
+            }
+            ASTNode::PseudoInstruction(..) => {
+                unreachable!("Pseudo-instructions must be expanded beforehand")
+            }
+            ASTNode::Location(location) => {
+                self.location = location.clone();
+                self.nodes.push(ASTNode::Location(location));
+            }
+        }
+    }
+
+    /// Returns the value of a symbol defined so far.
+    pub(crate) fn symbol(&self, symbol: &str) -> Option<isize> {
+        value(&self.symbols, symbol).ok()
+    }
+
+    /// The size of each instruction with the value its operands had in this
+    /// layout. Operands that can't be resolved or don't fit are left for the
+    /// final resolution to report, keeping the size they were laid out with.
+    fn resolved_sizes(&self) -> Vec<usize> {
+        self.nodes
+            .iter()
+            .filter_map(|node| match node {
+                ASTNode::Instruction(inst, ops) => Some((*inst, ops)),
+                _ => None,
+            })
+            .zip(&self.sizes)
+            .map(|((inst, ops), size)| {
+                resolve_all(&self.symbols, ops)
+                    .and_then(|ops| instruction_size(inst, &ops))
+                    .unwrap_or(*size)
+            })
+            .collect()
+    }
+
+    /// The address of the current location, in [`Architecture::address_unit`]s,
+    /// or `None` if it's in the middle of one.
+    fn address(&mut self) -> Option<isize> {
+        let location = self.current_section().location;
+        location
+            .is_multiple_of(A::address_unit())
+            .then(|| (location / A::address_unit()) as isize)
+    }
+
+    /// Replaces `.` with the current location in `operators`. It's left as is
+    /// in the middle of an addressable unit, to fail if it's ever evaluated.
+    fn here(&mut self, operators: &[PlausibleOperator<A>]) -> Rc<[PlausibleOperator<A>]> {
+        let Some(address) = self.address() else {
+            return operators.into();
+        };
+        let location = Expression::Value(address);
+        operators
+            .iter()
+            .map(|operator| operator.replace(".", &location))
+            .collect()
+    }
+
+    fn define(&mut self, symbol: String, value: Expression) {
+        if self.symbols.insert(symbol.clone(), value).is_some() {
+            error(
+                &self.location,
+                format!("Symbol {symbol} is already defined"),
+            );
+        }
+    }
+
+    fn current_section(&mut self) -> &mut SectionLayout {
+        let index = match self.current_section {
+            Some(index) => index,
+            None => self.switch_section(Section::DEFAULT.to_string(), None, None),
+        };
+        &mut self.sections[index]
+    }
+
+    fn switch_section(
+        &mut self,
+        name: String,
+        flags: Option<SectionFlags>,
+        base: Option<usize>,
+    ) -> usize {
+        let index = match self
+            .sections
+            .iter()
+            .position(|section| section.name == name)
+        {
+            Some(index) => {
+                let section = &self.sections[index];
+                if flags.is_some_and(|flags| flags != section.flags)
+                    || base.is_some_and(|base| base != section.base)
+                {
+                    error(
+                        &self.location,
+                        format!("Section {name} was already defined with different flags or base"),
+                    );
+                }
+                index
+            }
+            None => {
+                let base = base.unwrap_or(0);
+                self.sections.push(SectionLayout {
+                    flags: flags.unwrap_or_else(|| SectionFlags::default_for(&name)),
+                    name,
+                    base,
+                    location: base * A::address_unit(),
+                });
+                self.sections.len() - 1
+            }
+        };
+
+        let section = &self.sections[index];
+        self.nodes.push(ASTNode::Directive(Directive::Section {
+            name: section.name.clone(),
+            flags: Some(section.flags),
+            base: Some(section.base),
+        }));
+        self.current_section = Some(index);
+        index
+    }
+}
+
+fn instruction_size<A: Architecture>(
+    instruction: A::Instruction,
+    operands: &[PlausibleOperator<A>],
+) -> Result<usize, Box<dyn Error>> {
+    if let Some(size) = instruction.size(operands) {
+        return Ok(size);
+    }
+
+    let placeholders = operands
+        .iter()
+        .map(PlausibleOperator::or_placeholder)
+        .collect::<Vec<_>>();
+
+    Ok(instruction
+        .emit(parse_operands(instruction, &placeholders)?.iter().cloned())
+        .into_iter()
+        .count())
+}
+
+/// The value of `symbol`, evaluating the constants it refers to.
+fn value(symbols: &HashMap<String, Expression>, symbol: &str) -> Result<isize, Box<dyn Error>> {
+    // Each reference goes one constant deeper, so a cycle runs out of symbols
+    fn value_within(
+        symbols: &HashMap<String, Expression>,
+        symbol: &str,
+        depth: usize,
+    ) -> Result<isize, Box<dyn Error>> {
+        let expression = symbols
+            .get(symbol)
+            .ok_or_else(|| format!("Undefined symbol: {symbol}"))?;
+        let depth = depth
+            .checked_sub(1)
+            .ok_or_else(|| format!("{symbol} is defined in terms of itself"))?;
+        expression.try_evaluate(&|symbol| value_within(symbols, symbol, depth))
+    }
+
+    value_within(symbols, symbol, symbols.len())
+}
+
+fn resolve<A: Architecture>(
+    symbols: &HashMap<String, Expression>,
+    operator: &PlausibleOperator<A>,
+) -> Result<isize, Box<dyn Error>> {
+    let expression = match operator {
+        PlausibleOperator::Value(value) => return Ok(*value),
+        PlausibleOperator::Identifier(identifier) => Expression::Identifier(identifier.clone()),
+        PlausibleOperator::Expression(expression) => expression.clone(),
+        _ => return Err("Expected a value".into()),
+    };
+
+    expression
+        .try_evaluate(&|symbol| value(symbols, symbol))
+        .map_err(|error| format!("Can't evaluate {expression}: {error}").into())
+}
+
+fn resolve_all<A: Architecture>(
+    symbols: &HashMap<String, Expression>,
+    operators: &[PlausibleOperator<A>],
+) -> Result<Rc<[PlausibleOperator<A>]>, Box<dyn Error>> {
+    operators
+        .iter()
+        .map(|operator| match operator {
+            PlausibleOperator::Identifier(_) | PlausibleOperator::Expression(_) => {
+                Ok(PlausibleOperator::Value(resolve(symbols, operator)?))
+            }
+            operator => Ok(operator.clone()),
+        })
+        .collect()
+}
+
+/// Joins the strings and values of a diagnostic message.
+fn describe<A: Architecture>(message: &[PlausibleOperator<A>]) -> String {
+    if message.is_empty() {
+        return "Assertion failed".to_string();
+    }
+
+    message
+        .iter()
+        .map(|part| match part {
+            PlausibleOperator::String(string) => string.clone(),
+            PlausibleOperator::Value(value) => value.to_string(),
+            _ => unreachable!("Message wasn't resolved"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::architectures::test_arch::TestArch;
+    use crate::assembler::{AssemblerPasses, assemble};
+    use crate::define_architecture;
+
+    // Addresses count in 2-byte units, also used by the tests of other passes
+    define_architecture! {
+        pub(crate) architecture Wide {
+            fn address_unit() -> usize {
+                2
+            }
+        }
+        symbols WideSymbol {
+            Reg = "r" 0..8,
+        }
+        operand_kinds WideOperandKind {
+            Reg = symbol Reg,
+            Imm8s = signed 8,
+        }
+        instructions WideInstruction: u16 {
+            Movi = "movi" (Reg rd, Imm8s imm) => [op[15:12] = 5, rd[11:9], imm[7:0]],
+            Halt = "halt" () => [op[15:0] = 0xffff],
+        }
+    }
+
+    #[test]
+    fn resolves_directive_arguments_at_layout() {
+        let sections = assemble(
+            AssemblerPasses::<TestArch>::default(),
+            r#"
+            .equ RESET, 4
+            .equ RAM, 0x100
+            .org RESET
+            halt
+            .balign RESET * 2, 0xff
+            .byte 1
+            .section .data, "w", RAM
+            .byte 2
+            "#,
+        );
+
+        assert_eq!(
+            sections[0].bytes,
+            [
+                0, 0, 0, 0, 2, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 1
+            ]
+        );
+        assert_eq!(
+            (sections[1].name.as_str(), sections[1].base),
+            (".data", 0x100)
+        );
+        assert_eq!(sections[1].bytes, [2]);
+    }
+
+    #[test]
+    fn lays_out_again_until_sizes_settle() {
+        // li takes 3 bytes if its value fits in 8 bits, and 4 otherwise
+        let li = |start: usize| {
+            let source = format!(".org {start}\nli r1, end\nend:\n");
+            let sections = assemble(AssemblerPasses::<TestArch>::default(), &source);
+            sections[0].bytes[start..].to_vec()
+        };
+
+        assert_eq!(li(124), [4, 1, 127]);
+        assert_eq!(li(125), [5, 1, 129, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Undefined symbol: RESET")]
+    fn rejects_symbols_defined_after_the_directive() {
+        assemble(
+            AssemblerPasses::<TestArch>::default(),
+            ".org RESET\n.equ RESET, 4\n",
+        );
+    }
+
+    #[test]
+    fn counts_addresses_in_address_units() {
+        let sections = assemble(
+            AssemblerPasses::<Wide>::default(),
+            r#"
+            halt
+            .org 4
+            here: movi r1, here
+            .balign 4, 0xff
+            end: movi r2, end
+            "#,
+        );
+
+        assert_eq!(
+            sections[0].bytes,
+            [
+                0xff, 0xff, 0, 0, 0, 0, 0, 0, 0x04, 0x52, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x08,
+                0x54
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Alignment 0x8000000000000000 is out of range")]
+    fn rejects_alignments_out_of_range() {
+        assemble(AssemblerPasses::<Wide>::default(), "halt\n.align 63\n");
+    }
+}
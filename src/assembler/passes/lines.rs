@@ -0,0 +1,39 @@
+use crate::assembler::AssemblerPass;
+use crate::assembler::passes::tokenize::Token;
+
+/// A pass on tokens that works on whole lines, so tokens are held back until
+/// the end of each line. Implementing it implements [`AssemblerPass`].
+pub trait LinePass {
+    /// The tokens of the line being read.
+    fn line(&mut self) -> &mut Vec<Token>;
+
+    /// Transforms a whole line, given without its line feed.
+    fn process_line(&mut self, line: Vec<Token>) -> Vec<Token>;
+
+    /// Called once the last line is processed, to report unterminated blocks.
+    fn finish_lines(&mut self) {}
+}
+
+impl<P: LinePass> AssemblerPass for P {
+    type Input = Token;
+    type Output = Token;
+
+    fn apply(&mut self, item: Self::Input) -> impl IntoIterator<Item = Self::Output> {
+        if item != Token::LineFeed {
+            self.line().push(item);
+            return vec![];
+        }
+
+        let line = std::mem::take(self.line());
+        let mut output = self.process_line(line);
+        output.push(Token::LineFeed);
+        output
+    }
+
+    fn finish(&mut self) -> impl IntoIterator<Item = Self::Output> {
+        let line = std::mem::take(self.line());
+        let output = self.process_line(line);
+        self.finish_lines();
+        output
+    }
+}
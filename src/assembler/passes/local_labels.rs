@@ -0,0 +1,191 @@
+use crate::assembler::diagnostics::{Location, error};
+use crate::assembler::passes::lines::LinePass;
+use crate::assembler::passes::tokenize::{Token, location};
+use std::collections::HashMap;
+
+/// Gives a unique name to numeric local labels.
+///
+/// ```text
+/// 1:  addi r1, r1, -1
+///     bnz r1, 1b
+///     bz r2, 1f
+/// 1:  halt
+/// ```
+///
+/// `1b` refers to the closest `1:` before the reference, and `1f` to the
+/// closest one after it.
+///
+/// Numeric labels can be defined any number of times, and each definition is
+/// renamed to a name that can't appear in the source. Runs after the macro
+/// pass, so they can also be used inside macros.
+#[derive(Default)]
+pub struct LocalLabelPass {
+    line: Vec<Token>,
+    location: Location,
+    /// How many times each label was defined so far
+    definitions: HashMap<isize, usize>,
+    /// The last forward reference to each label that isn't defined yet
+    forward_references: HashMap<isize, (usize, Location)>,
+}
+
+impl LinePass for LocalLabelPass {
+    fn line(&mut self) -> &mut Vec<Token> {
+        &mut self.line
+    }
+
+    fn process_line(&mut self, line: Vec<Token>) -> Vec<Token> {
+        if let Some(location) = location(&line) {
+            self.location = location.clone();
+        }
+
+        let mut output = Vec::with_capacity(line.len());
+        let mut tokens = line.into_iter().peekable();
+
+        // Labels can only be defined at the start of the line
+        while let Some(token) = tokens.next_if(|token| {
+            matches!(
+                token,
+                Token::Location(_) | Token::Symbol(_) | Token::Value(_)
+            )
+        }) {
+            match (token, tokens.next_if_eq(&Token::Colon)) {
+                (Token::Location(location), _) => output.push(Token::Location(location)),
+                (Token::Value(label), Some(colon)) => {
+                    output.push(Token::Symbol(self.define(label)));
+                    output.push(colon);
+                }
+                (Token::Symbol(label), Some(_))
+                    if parse_local_label_reference(&label).is_some() =>
+                {
+                    error(&self.location, format!("Invalid label: {label}"))
+                }
+                (Token::Symbol(label), Some(colon)) => {
+                    output.push(Token::Symbol(label));
+                    output.push(colon);
+                }
+                (token, None) => {
+                    output.push(self.reference(token));
+                    break;
+                }
+                (_, Some(_)) => unreachable!("Only labels can be followed by a colon here"),
+            }
+        }
+
+        output.extend(tokens.map(|token| self.reference(token)));
+        output
+    }
+
+    fn finish_lines(&mut self) {
+        if let Some((label, (_, location))) = self.forward_references.iter().next() {
+            error(location, format!("Local label {label} is never defined"));
+        }
+    }
+}
+
+impl LocalLabelPass {
+    fn define(&mut self, label: isize) -> String {
+        let definitions = self.definitions.entry(label).or_default();
+        *definitions += 1;
+
+        if self
+            .forward_references
+            .get(&label)
+            .is_some_and(|(definition, _)| *definition <= *definitions)
+        {
+            self.forward_references.remove(&label);
+        }
+
+        local_label_name(label, *definitions)
+    }
+
+    fn reference(&mut self, token: Token) -> Token {
+        let Token::Symbol(symbol) = &token else {
+            return token;
+        };
+        let Some((label, direction)) = parse_local_label_reference(symbol) else {
+            return token;
+        };
+
+        let definitions = self.definitions.get(&label).copied().unwrap_or(0);
+        let definition = match direction {
+            'b' if definitions == 0 => error(
+                &self.location,
+                format!("Local label {label} isn't defined yet"),
+            ),
+            'b' => definitions,
+            _ => {
+                self.forward_references
+                    .insert(label, (definitions + 1, self.location.clone()));
+                definitions + 1
+            }
+        };
+
+        Token::Symbol(local_label_name(label, definition))
+    }
+}
+
+/// Names the `definition`th definition of a local label. The name contains a
+/// `@`, so it can't clash with the labels in the source.
+fn local_label_name(label: isize, definition: usize) -> String {
+    format!("{label}@local{definition}")
+}
+
+/// Whether `name` was given to a local label by this pass.
+pub(crate) fn is_local_label_name(name: &str) -> bool {
+    name.split_once("@local")
+        .is_some_and(|(label, definition)| {
+            label.parse::<isize>().is_ok() && definition.parse::<usize>().is_ok()
+        })
+}
+
+/// Splits a local label reference, like `1b` or `1f`, into its label and direction.
+pub(crate) fn parse_local_label_reference(reference: &str) -> Option<(isize, char)> {
+    let direction = reference
+        .chars()
+        .last()
+        .filter(|c| matches!(c, 'b' | 'f'))?;
+    let label = &reference[..reference.len() - 1];
+
+    if label.is_empty() || !label.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((label.parse().ok()?, direction))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::AssemblerPass;
+    use crate::assembler::passes::tokenize::{normalize, tokenize};
+
+    fn rename(source: &str) -> Vec<Token> {
+        normalize(LocalLabelPass::default().apply_all(tokenize(source)))
+    }
+
+    #[test]
+    fn refers_to_the_closest_definition() {
+        let source = "1: jump 1f\n1: jump 1b\njump 1b\n2: jump 1b";
+        let expected = tokenize("a: jump b\nb: jump b\njump b\nc: jump b")
+            .into_iter()
+            .map(|token| match token {
+                Token::Symbol(symbol) if symbol == "a" => Token::Symbol(local_label_name(1, 1)),
+                Token::Symbol(symbol) if symbol == "b" => Token::Symbol(local_label_name(1, 2)),
+                Token::Symbol(symbol) if symbol == "c" => Token::Symbol(local_label_name(2, 1)),
+                token => token,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rename(source), expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "Local label 1 is never defined")]
+    fn rejects_forward_references_never_defined() {
+        rename("1: jump 1b\njump 1f");
+    }
+
+    #[test]
+    #[should_panic(expected = "Local label 1 isn't defined yet")]
+    fn rejects_backward_references_not_defined_yet() {
+        rename("jump 1b\n1: halt");
+    }
+}
@@ -0,0 +1,531 @@
+use crate::assembler::AssemblerPass;
+use crate::assembler::diagnostics::{Location, error};
+use crate::assembler::expression::Expression;
+use crate::assembler::passes::lines::LinePass;
+use crate::assembler::passes::tokenize::{Token, TokenizePass, labels, location, statement};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::rc::Rc;
+
+/// Macros can invoke other macros, but not indefinitely.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// Records `.macro` definitions and expands their invocations inline.
+///
+/// ```text
+/// .macro name a, b=1, rest:vararg
+///     ...
+/// .endm
+/// ```
+///
+/// Parameters are referenced as `\a` inside the body. Invocations accept
+/// positional arguments followed by named ones (`name 1, b=2`), parameters
+/// without an argument take their default value, and a trailing `:vararg`
+/// parameter takes all the remaining arguments. Labels defined inside the
+/// body are renamed on each expansion, so a macro can be invoked many times.
+/// Expanded lines keep their location in the body, along with the invocation's.
+///
+/// Also expands repetitions, which may appear inside macros and vice versa:
+///
+/// ```text
+/// .rept count        .irp name, value, ...        .irpc name, text
+///     ...                ...                          ...
+/// .endr              .endr                        .endr
+/// ```
+///
+/// `.rept` repeats its body `count` times, which must be a constant. `.irp`
+/// repeats it once per value, bound to `\name`, and `.irpc` once per character
+/// of `text`. The location of expanded lines also tells the iteration.
+#[derive(Default)]
+pub struct MacroPass {
+    macros: HashMap<String, Rc<Macro>>,
+    line: Vec<Token>,
+    definition: Option<MacroDefinition>,
+    repetition: Option<RepetitionDefinition>,
+    expansions: usize,
+    location: Location,
+}
+
+/// The tokens bound to each parameter.
+type Arguments = HashMap<String, Vec<Token>>;
+
+struct Macro {
+    parameters: Vec<MacroParameter>,
+    body: Vec<Vec<Token>>,
+    local_labels: HashSet<String>,
+}
+
+struct MacroParameter {
+    name: String,
+    default: Option<Vec<Token>>,
+    vararg: bool,
+}
+
+struct MacroDefinition {
+    name: String,
+    parameters: Vec<MacroParameter>,
+    body: Vec<Vec<Token>>,
+    depth: usize,
+    location: Location,
+}
+
+struct RepetitionDefinition {
+    /// The arguments bound on each iteration
+    iterations: Vec<Arguments>,
+    body: Vec<Vec<Token>>,
+    depth: usize,
+    location: Location,
+}
+
+impl LinePass for MacroPass {
+    fn line(&mut self) -> &mut Vec<Token> {
+        &mut self.line
+    }
+
+    fn process_line(&mut self, line: Vec<Token>) -> Vec<Token> {
+        self.expand_line(line, 0)
+    }
+
+    fn finish_lines(&mut self) {
+        if let Some(definition) = &self.definition {
+            error(
+                &definition.location,
+                format!("Unterminated macro: {}", definition.name),
+            );
+        }
+        if let Some(repetition) = &self.repetition {
+            error(&repetition.location, "Missing .endr");
+        }
+    }
+}
+
+impl MacroPass {
+    fn expand_line(&mut self, line: Vec<Token>, depth: usize) -> Vec<Token> {
+        if let Some(location) = location(&line) {
+            self.location = location.clone();
+        }
+
+        if let Some(definition) = &mut self.definition {
+            match statement(&line).first() {
+                Some(Token::Symbol(directive)) if directive == ".macro" => definition.depth += 1,
+                Some(Token::Symbol(directive)) if directive == ".endm" => {
+                    if definition.depth == 0 {
+                        let definition = self.definition.take().unwrap();
+                        self.define(definition);
+                        return vec![];
+                    }
+                    definition.depth -= 1;
+                }
+                _ => {}
+            }
+            definition.body.push(line);
+            return vec![];
+        }
+
+        if let Some(repetition) = &mut self.repetition {
+            match statement(&line).first() {
+                Some(Token::Symbol(directive)) if is_repetition(directive) => repetition.depth += 1,
+                Some(Token::Symbol(directive)) if directive == ".endr" => {
+                    if repetition.depth == 0 {
+                        let repetition = self.repetition.take().unwrap();
+                        return self.repeat(repetition, depth);
+                    }
+                    repetition.depth -= 1;
+                }
+                _ => {}
+            }
+            repetition.body.push(line);
+            return vec![];
+        }
+
+        let labels = &line[..line.len() - statement(&line).len()];
+        match statement(&line) {
+            [Token::Symbol(directive), parameters @ ..] if directive == ".macro" => {
+                let [Token::Symbol(name), parameters @ ..] = parameters else {
+                    error(&self.location, "Expected a macro name");
+                };
+                self.definition = Some(MacroDefinition {
+                    name: name.clone(),
+                    parameters: parse_parameters(parameters)
+                        .unwrap_or_else(|message| error(&self.location, message)),
+                    body: vec![],
+                    depth: 0,
+                    location: self.location.clone(),
+                });
+                labels.to_vec()
+            }
+            [Token::Symbol(directive), ..] if directive == ".endm" => {
+                error(&self.location, "Unexpected .endm outside of a macro")
+            }
+            [Token::Symbol(directive), arguments @ ..] if is_repetition(directive) => {
+                self.repetition = Some(RepetitionDefinition {
+                    iterations: parse_iterations(directive, arguments)
+                        .unwrap_or_else(|message| error(&self.location, message)),
+                    body: vec![],
+                    depth: 0,
+                    location: self.location.clone(),
+                });
+                labels.to_vec()
+            }
+            [Token::Symbol(directive), ..] if directive == ".endr" => {
+                error(&self.location, "Unexpected .endr outside of a repetition")
+            }
+            [Token::Symbol(name), arguments @ ..] if self.macros.contains_key(name) => {
+                if depth >= MAX_EXPANSION_DEPTH {
+                    error(&self.location, format!("Macro {name} is nested too deeply"));
+                }
+
+                let mut output = labels.to_vec();
+                let expansion = self
+                    .expand(name, arguments)
+                    .unwrap_or_else(|message| error(&self.location, message));
+                for line in expansion {
+                    output.extend(self.expand_line(line, depth + 1));
+                    output.push(Token::LineFeed);
+                }
+                output
+            }
+            _ => line,
+        }
+    }
+
+    fn repeat(&mut self, repetition: RepetitionDefinition, depth: usize) -> Vec<Token> {
+        let mut output = vec![];
+
+        for (i, arguments) in repetition.iterations.iter().enumerate() {
+            let iteration = repetition.location.in_iteration(i + 1);
+            for line in &repetition.body {
+                let line = line
+                    .iter()
+                    .flat_map(|token| match token {
+                        Token::Symbol(symbol)
+                            if let Some(value) = symbol
+                                .strip_prefix('\\')
+                                .and_then(|parameter| arguments.get(parameter)) =>
+                        {
+                            value.clone()
+                        }
+                        Token::Location(location) => {
+                            vec![Token::Location(location.expanded_from(&iteration))]
+                        }
+                        token => vec![token.clone()],
+                    })
+                    .collect();
+                output.extend(self.expand_line(line, depth));
+                output.push(Token::LineFeed);
+            }
+        }
+
+        output
+    }
+
+    fn define(&mut self, definition: MacroDefinition) {
+        let local_labels = definition
+            .body
+            .iter()
+            .flat_map(|line| labels(line))
+            .filter(|label| !label.starts_with('\\'))
+            .cloned()
+            .collect();
+
+        let r#macro = Macro {
+            parameters: definition.parameters,
+            body: definition.body,
+            local_labels,
+        };
+
+        if self
+            .macros
+            .insert(definition.name.clone(), Rc::new(r#macro))
+            .is_some()
+        {
+            error(
+                &definition.location,
+                format!("Macro {} is already defined", definition.name),
+            );
+        }
+    }
+
+    fn expand(
+        &mut self,
+        name: &str,
+        arguments: &[Token],
+    ) -> Result<Vec<Vec<Token>>, Box<dyn Error>> {
+        let r#macro = self.macros[name].clone();
+        let arguments = bind_arguments(name, &r#macro.parameters, arguments)?;
+
+        self.expansions += 1;
+        let suffix = format!("@{}", self.expansions);
+
+        // The parameters of the repetitions in the body, innermost last, which
+        // are left for the repetition to substitute
+        let mut repetitions: Vec<Option<&str>> = vec![];
+        let mut expansion = vec![];
+        for line in &r#macro.body {
+            match statement(line) {
+                [Token::Symbol(directive), Token::Symbol(parameter), ..]
+                    if directive == ".irp" || directive == ".irpc" =>
+                {
+                    repetitions.push(Some(parameter))
+                }
+                [Token::Symbol(directive), ..] if directive == ".rept" => repetitions.push(None),
+                [Token::Symbol(directive), ..] if directive == ".endr" => {
+                    repetitions.pop();
+                }
+                _ => {}
+            }
+            let bound = |parameter: &str| repetitions.contains(&Some(parameter));
+
+            let tokens = line
+                .iter()
+                .map(|token| match token {
+                    Token::Symbol(symbol) if symbol.starts_with('\\') && bound(&symbol[1..]) => {
+                        Ok(vec![token.clone()])
+                    }
+                    Token::Symbol(symbol) if symbol.starts_with('\\') => {
+                        arguments.get(&symbol[1..]).cloned().ok_or_else(|| {
+                            format!("Macro {name} has no parameter {}", &symbol[1..]).into()
+                        })
+                    }
+                    Token::Symbol(symbol) if r#macro.local_labels.contains(symbol) => {
+                        Ok(vec![Token::Symbol(symbol.clone() + &suffix)])
+                    }
+                    Token::Location(location) => Ok(vec![Token::Location(
+                        location.expanded_from(&self.location),
+                    )]),
+                    token => Ok(vec![token.clone()]),
+                })
+                .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+            expansion.push(tokens.concat());
+        }
+        Ok(expansion)
+    }
+}
+
+fn is_repetition(directive: &str) -> bool {
+    matches!(directive, ".rept" | ".irp" | ".irpc")
+}
+
+fn parse_iterations(
+    directive: &str,
+    arguments: &[Token],
+) -> Result<Vec<Arguments>, Box<dyn Error>> {
+    match (directive, arguments) {
+        (".rept", count) => {
+            let count = Expression::parse(count)?
+                .evaluate_constant()
+                .and_then(|count| usize::try_from(count).ok())
+                .ok_or("The repetition count must be a constant, positive value")?;
+            Ok(vec![HashMap::new(); count])
+        }
+        (".irp", [Token::Symbol(_)]) => Ok(vec![]),
+        (".irp", [Token::Symbol(parameter), Token::Comma, values @ ..]) => {
+            Ok(split_arguments(values)
+                .into_iter()
+                .map(|value| HashMap::from([(parameter.clone(), value.to_vec())]))
+                .collect())
+        }
+        (".irpc", [Token::Symbol(parameter), Token::Comma, text]) => {
+            let text = match text {
+                Token::Symbol(text) | Token::String(text) => text.clone(),
+                Token::Value(value) => value.to_string(),
+                _ => return Err("Expected a text to iterate over".into()),
+            };
+            Ok(text
+                .chars()
+                .map(|character| {
+                    let tokens = TokenizePass::default()
+                        .apply_all([character])
+                        .into_iter()
+                        .filter(|token| !matches!(token, Token::Location(_)))
+                        .collect();
+                    HashMap::from([(parameter.clone(), tokens)])
+                })
+                .collect())
+        }
+        _ => Err(format!("Invalid arguments for {directive}").into()),
+    }
+}
+
+fn split_arguments(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        vec![]
+    } else {
+        tokens.split(|token| *token == Token::Comma).collect()
+    }
+}
+
+fn parse_parameters(tokens: &[Token]) -> Result<Vec<MacroParameter>, Box<dyn Error>> {
+    let parameters = split_arguments(tokens)
+        .into_iter()
+        .map(|parameter| match parameter {
+            [Token::Symbol(name)] => Ok(MacroParameter {
+                name: name.clone(),
+                default: None,
+                vararg: false,
+            }),
+            [Token::Symbol(name), Token::Equals, default @ ..] => Ok(MacroParameter {
+                name: name.clone(),
+                default: Some(default.to_vec()),
+                vararg: false,
+            }),
+            [Token::Symbol(name), Token::Colon, Token::Symbol(qualifier)]
+                if qualifier == "vararg" =>
+            {
+                Ok(MacroParameter {
+                    name: name.clone(),
+                    default: None,
+                    vararg: true,
+                })
+            }
+            _ => Err(format!("Invalid macro parameter: {parameter:?}")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(position) = parameters.iter().position(|parameter| parameter.vararg)
+        && position != parameters.len() - 1
+    {
+        return Err("Only the last macro parameter can be a vararg".into());
+    }
+
+    Ok(parameters)
+}
+
+fn bind_arguments(
+    name: &str,
+    parameters: &[MacroParameter],
+    tokens: &[Token],
+) -> Result<Arguments, Box<dyn Error>> {
+    let mut positional = vec![];
+    let mut named = HashMap::new();
+
+    for argument in split_arguments(tokens) {
+        match argument {
+            [Token::Symbol(parameter), Token::Equals, value @ ..] => {
+                named.insert(parameter.clone(), value.to_vec());
+            }
+            _ if !named.is_empty() => {
+                return Err(
+                    format!("Positional arguments of {name} must come before named ones").into(),
+                );
+            }
+            value => positional.push(value.to_vec()),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let mut bound = HashMap::new();
+
+    for parameter in parameters {
+        let value = if parameter.vararg {
+            let rest = positional.by_ref().collect::<Vec<_>>().join(&Token::Comma);
+            named.remove(&parameter.name).unwrap_or(rest)
+        } else {
+            match (positional.next(), named.remove(&parameter.name)) {
+                (Some(_), Some(_)) => {
+                    return Err(
+                        format!("Argument {} for {name} is given twice", parameter.name).into(),
+                    );
+                }
+                (value, named_value) => value
+                    .or(named_value)
+                    .or_else(|| parameter.default.clone())
+                    .ok_or_else(|| format!("Missing argument {} for {name}", parameter.name))?,
+            }
+        };
+        bound.insert(parameter.name.clone(), value);
+    }
+
+    if positional.next().is_some() {
+        return Err(format!("Too many arguments for {name}").into());
+    }
+    if let Some(parameter) = named.keys().next() {
+        return Err(format!("Macro {name} has no parameter {parameter}").into());
+    }
+
+    Ok(bound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::passes::tokenize::{normalize, tokenize};
+
+    fn expand(source: &str) -> Vec<Token> {
+        normalize(MacroPass::default().apply_all(tokenize(source)))
+    }
+
+    #[test]
+    fn binds_arguments_to_parameters() {
+        let source = r"
+            .macro pair first, second=2
+            .byte \first, \second
+            .endm
+            .macro bytes values:vararg
+            .byte \values
+            .endm
+            pair 1
+            pair 1, second=3
+            pair 4, 5
+            bytes 6, 7, 8
+        ";
+        let expected = tokenize(".byte 1, 2\n.byte 1, 3\n.byte 4, 5\n.byte 6, 7, 8");
+        assert_eq!(expand(source), expected);
+    }
+
+    #[test]
+    fn renames_labels_on_each_expansion() {
+        let source = ".macro spin\nagain: jump again\n.endm\nspin\nspin";
+        let expected = tokenize("first: jump first\nsecond: jump second")
+            .into_iter()
+            .map(|token| match token {
+                Token::Symbol(symbol) if symbol == "first" => Token::Symbol("again@1".into()),
+                Token::Symbol(symbol) if symbol == "second" => Token::Symbol("again@2".into()),
+                token => token,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(expand(source), expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "Macro forever is nested too deeply")]
+    fn rejects_unbounded_recursion() {
+        expand(".macro forever\nforever\n.endm\nforever");
+    }
+
+    #[test]
+    fn repeats_lines() {
+        let source = r"
+            .rept 2
+            .byte 1
+            .endr
+            .rept 0
+            .byte 2
+            .endr
+            .irp value, 3, 4
+            .byte \value
+            .endr
+            .irpc digit, 56
+            .byte \digit
+            .endr
+        ";
+        let expected = tokenize(".byte 1\n.byte 1\n.byte 3\n.byte 4\n.byte 5\n.byte 6");
+        assert_eq!(expand(source), expected);
+    }
+
+    #[test]
+    fn repeats_lines_inside_macros() {
+        let source = r"
+            .macro bytes values:vararg
+            .irp value, \values
+            .rept 2
+            .byte \value
+            .endr
+            .endr
+            .endm
+            bytes 1, 2
+        ";
+        let expected = tokenize(".byte 1\n.byte 1\n.byte 2\n.byte 2");
+        assert_eq!(expand(source), expected);
+    }
+}
@@ -1,5 +1,17 @@
+pub mod aliases;
+pub mod conditionals;
 pub mod emit;
+pub mod include;
+pub mod layout;
+pub mod lines;
+pub mod local_labels;
+pub mod macros;
 pub mod parse;
 pub mod parse_operands;
+pub mod pseudo_instructions;
+pub mod relaxation;
 pub mod retokenize;
+pub mod scopes;
+pub mod structs;
 pub mod tokenize;
+pub mod validate;
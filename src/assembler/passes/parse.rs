@@ -1,19 +1,29 @@
-use crate::arch_def::{Architecture, Instruction, OperandKind};
+use crate::arch_def::index::InstructionIndex;
+use crate::arch_def::{Architecture, Extensions, Instruction, OperandKind};
 use crate::assembler::AssemblerPass;
+use crate::assembler::diagnostics::{Location, error};
+use crate::assembler::directives::Directive;
+use crate::assembler::expression::Expression;
+use crate::assembler::passes::parse_operands::parse_operands;
 use crate::assembler::passes::retokenize::ArchToken;
-use itertools::{EitherOrBoth, Itertools, zip_eq};
+use crate::assembler::passes::tokenize::Token;
+use itertools::Itertools;
+use std::error::Error;
 use std::fmt::{Debug, Formatter};
-use std::iter::zip;
 use std::rc::Rc;
 
 pub struct ParsePass<A: Architecture> {
     state: ParserState<A>,
+    location: Location,
+    extensions: Extensions,
 }
 
 impl<A: Architecture> Default for ParsePass<A> {
     fn default() -> Self {
         Self {
             state: ParserState::default(),
+            location: Location::default(),
+            extensions: Extensions::default(),
         }
     }
 }
@@ -27,37 +37,63 @@ impl<A: Architecture> AssemblerPass for ParsePass<A> {
             // Skip over line feeds
             (ParserState::Initial, ArchToken::LineFeed) => (ParserState::Initial, None),
 
-            // Parse instruction
-            (ParserState::Initial, ArchToken::Instruction(inst)) => {
-                (ParserState::InInstruction(InInstruction::start(inst)), None)
+            // Keep track of locations, and pass them on to later passes
+            (ParserState::Initial, ArchToken::Location(location)) => {
+                self.location = location.clone();
+                (ParserState::Initial, Some(ASTNode::Location(location)))
             }
-            (ParserState::InInstruction(inst), ArchToken::Symbol(symbol))
-                if inst.can_accept_operator =>
-            {
-                (
-                    ParserState::InInstruction(
-                        inst.with_operator(PlausibleOperator::Symbol(symbol)),
-                    ),
-                    None,
-                )
-            }
-            (ParserState::InInstruction(inst), ArchToken::Value(value))
-                if inst.can_accept_operator =>
-            {
-                (
-                    ParserState::InInstruction(inst.with_operator(PlausibleOperator::Value(value))),
-                    None,
-                )
-            }
-            (ParserState::InInstruction(inst), ArchToken::Comma) if !inst.can_accept_operator => {
-                (ParserState::InInstruction(inst.with_comma()), None)
+
+            // Parse label
+            (ParserState::Initial, ArchToken::Identifier(label)) => {
+                (ParserState::InLabel(label), None)
             }
-            (state @ ParserState::InInstruction(inst), ArchToken::LineFeed) if inst.can_finish => {
-                (ParserState::Initial, Some(state.finish_or_error()))
+            (ParserState::InLabel(label), ArchToken::Colon) => {
+                (ParserState::Initial, Some(ASTNode::Label(label.clone())))
             }
+            // Scoped labels, like `.done:`, look like directives
+            (ParserState::InStatement(stmt), ArchToken::Colon) if stmt.is_scoped_label() => (
+                ParserState::Initial,
+                Some(ASTNode::Label(stmt.name.clone())),
+            ),
+
+            // Parse instruction or directive
+            (ParserState::Initial, ArchToken::Instruction(inst)) => (
+                ParserState::InStatement(InStatement::start(StatementKind::Instruction, inst)),
+                None,
+            ),
+            (ParserState::Initial, ArchToken::Directive(directive)) => (
+                ParserState::InStatement(InStatement::start(StatementKind::Directive, directive)),
+                None,
+            ),
+            (ParserState::InStatement(stmt), ArchToken::Comma) if stmt.can_accept_comma() => (
+                ParserState::InStatement(
+                    stmt.with_comma()
+                        .unwrap_or_else(|message| error(&self.location, message)),
+                ),
+                None,
+            ),
+            (state @ ParserState::InStatement(stmt), ArchToken::LineFeed) if stmt.can_finish() => (
+                ParserState::Initial,
+                Some(state.finish_or_error(&self.extensions, &self.location)),
+            ),
+            (
+                ParserState::InStatement(stmt),
+                token @ (ArchToken::Symbol(_)
+                | ArchToken::Identifier(_)
+                | ArchToken::Directive(_)
+                | ArchToken::Value(_)
+                | ArchToken::String(_)
+                | ArchToken::Operator(_)),
+            ) => (ParserState::InStatement(stmt.with_token(token)), None),
+
+            // An identifier that isn't a label starts an unknown statement
+            (ParserState::InLabel(name), _) => error(
+                &self.location,
+                format!("Unknown instruction or directive: {name}"),
+            ),
 
             // Fail for anything else
-            _ => panic!("Unexpected token"),
+            _ => error(&self.location, "Unexpected token"),
         };
 
         self.state = next_state;
@@ -65,7 +101,17 @@ impl<A: Architecture> AssemblerPass for ParsePass<A> {
     }
 
     fn finish(&mut self) -> impl IntoIterator<Item = Self::Output> {
-        self.state.finish()
+        self.state
+            .finish(&self.extensions)
+            .unwrap_or_else(|message| error(&self.location, message))
+    }
+}
+
+impl<A: Architecture> ParsePass<A> {
+    /// Only accepts the instructions of the given extensions.
+    pub fn with_extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions = extensions;
+        self
     }
 }
 
@@ -73,82 +119,162 @@ impl<A: Architecture> AssemblerPass for ParsePass<A> {
 enum ParserState<A: Architecture> {
     #[default]
     Initial,
-    InInstruction(InInstruction<A>),
+    InLabel(String),
+    InStatement(InStatement<A>),
 }
 
 impl<A: Architecture> ParserState<A> {
-    fn finish(&self) -> Option<ASTNode<A>> {
+    fn finish(&self, extensions: &Extensions) -> Result<Option<ASTNode<A>>, Box<dyn Error>> {
         match self {
-            ParserState::Initial => None,
-            ParserState::InInstruction(inst) => inst.finish(),
+            ParserState::Initial => Ok(None),
+            ParserState::InLabel(_) => Ok(None),
+            ParserState::InStatement(stmt) => stmt.finish(extensions),
         }
     }
 
-    fn finish_or_error(&self) -> ASTNode<A> {
-        self.finish().unwrap_or_else(|| panic!("Unfinished node"))
+    fn finish_or_error(&self, extensions: &Extensions, location: &Location) -> ASTNode<A> {
+        match self.finish(extensions) {
+            Ok(Some(node)) => node,
+            Ok(None) => error(location, "Unfinished statement"),
+            Err(message) => error(location, message),
+        }
     }
 }
 
-struct InInstruction<A: Architecture> {
-    instruction: String,
+#[derive(Clone, Copy)]
+enum StatementKind {
+    Instruction,
+    Directive,
+}
+
+struct InStatement<A: Architecture> {
+    kind: StatementKind,
+    name: String,
     operators: Vec<PlausibleOperator<A>>,
-    can_accept_operator: bool,
-    can_finish: bool,
+    /// The tokens of the operator being read
+    operator: Vec<ArchToken<A>>,
 }
 
-impl<A: Architecture> InInstruction<A> {
-    fn start(instruction: String) -> Self {
+impl<A: Architecture> InStatement<A> {
+    fn start(kind: StatementKind, name: String) -> Self {
         Self {
-            instruction,
+            kind,
+            name,
             operators: vec![],
-            can_accept_operator: true,
-            can_finish: true,
+            operator: vec![],
         }
     }
 
-    fn with_operator(&self, operator: PlausibleOperator<A>) -> Self {
-        let mut operators = self.operators.clone();
-        operators.push(operator);
+    fn with_token(&self, token: ArchToken<A>) -> Self {
+        let mut operator = self.operator.clone();
+        operator.push(token);
         Self {
-            instruction: self.instruction.clone(),
-            operators,
-            can_accept_operator: false,
-            can_finish: true,
+            kind: self.kind,
+            name: self.name.clone(),
+            operators: self.operators.clone(),
+            operator,
         }
     }
 
-    fn with_comma(&self) -> Self {
-        Self {
-            instruction: self.instruction.clone(),
-            operators: self.operators.clone(),
-            can_accept_operator: true,
-            can_finish: false,
+    fn with_comma(&self) -> Result<Self, Box<dyn Error>> {
+        let mut operators = self.operators.clone();
+        operators.push(PlausibleOperator::from_tokens(&self.operator)?);
+        Ok(Self {
+            kind: self.kind,
+            name: self.name.clone(),
+            operators,
+            operator: vec![],
+        })
+    }
+
+    fn can_accept_comma(&self) -> bool {
+        !self.operator.is_empty()
+    }
+
+    fn is_scoped_label(&self) -> bool {
+        matches!(self.kind, StatementKind::Directive)
+            && self.operators.is_empty()
+            && self.operator.is_empty()
+    }
+
+    fn can_finish(&self) -> bool {
+        !self.operator.is_empty() || self.operators.is_empty()
+    }
+
+    fn all_operators(&self) -> Result<Vec<PlausibleOperator<A>>, Box<dyn Error>> {
+        let mut operators = self.operators.clone();
+        if !self.operator.is_empty() {
+            operators.push(PlausibleOperator::from_tokens(&self.operator)?);
         }
+        Ok(operators)
     }
 
-    fn finish(&self) -> Option<ASTNode<A>> {
-        if self.can_finish {
-            let inst = A::Instruction::enumerate()
-                .into_iter()
-                .filter(|inst| inst.name() == self.instruction)
-                .find(|inst| {
-                    inst.operands()
-                        .into_iter()
-                        .zip_longest(&self.operators)
-                        .all(|x| {
-                            x.both()
-                                .is_some_and(|(kind, operator)| kind.matches(operator))
-                        })
-                })?;
-            Some(ASTNode::Instruction(*inst, self.operators.clone().into()))
-        } else {
-            None
+    fn finish(&self, extensions: &Extensions) -> Result<Option<ASTNode<A>>, Box<dyn Error>> {
+        if !self.can_finish() {
+            return Ok(None);
+        }
+
+        let operators = self.all_operators()?;
+        match self.kind {
+            StatementKind::Instruction => {
+                let overloads = InstructionIndex::<A>::get().overloads(&self.name, operators.len());
+                let (enabled, disabled): (Vec<&A::Instruction>, Vec<_>) = overloads
+                    .iter()
+                    .filter(|inst| accepts(**inst, &operators))
+                    .partition(|inst| extensions.check::<A>(inst).is_ok());
+
+                match (enabled.first(), disabled.first()) {
+                    (Some(inst), _) => Ok(Some(ASTNode::Instruction(**inst, operators.into()))),
+                    _ if A::pseudo_instructions().contains(&self.name.as_str()) => Ok(Some(
+                        ASTNode::PseudoInstruction(self.name.clone(), operators.into()),
+                    )),
+                    (None, Some(inst)) => Err(extensions.check::<A>(inst).unwrap_err()),
+                    // Without overloads to pick from, say which operand doesn't fit
+                    (None, None) => Err(match overloads {
+                        [inst] => {
+                            let placeholders = operators
+                                .iter()
+                                .map(PlausibleOperator::or_placeholder)
+                                .collect::<Vec<_>>();
+                            parse_operands(*inst, &placeholders).err()
+                        }
+                        _ => None,
+                    }
+                    .unwrap_or_else(|| format!("Invalid operands for {}", self.name).into())),
+                }
+            }
+            StatementKind::Directive => Ok(Some(ASTNode::Directive(Directive::parse(
+                &self.name, &operators,
+            )?))),
         }
     }
 }
 
+/// Whether `instruction` accepts `operators`, standing in placeholders for the
+/// operators only known after layout.
+pub(crate) fn accepts<A: Architecture>(
+    instruction: A::Instruction,
+    operators: &[PlausibleOperator<A>],
+) -> bool {
+    instruction
+        .operands()
+        .into_iter()
+        .zip_longest(operators)
+        .all(|x| {
+            x.both()
+                .is_some_and(|(kind, operator)| kind.matches(&operator.or_placeholder()))
+        })
+}
+
+#[derive(Clone)]
 pub enum ASTNode<A: Architecture> {
     Instruction(A::Instruction, Rc<[PlausibleOperator<A>]>),
+    /// A pseudo-instruction, expanded into instructions before layout.
+    PseudoInstruction(String, Rc<[PlausibleOperator<A>]>),
+    Label(String),
+    Directive(Directive<A>),
+    /// The location of the following nodes, for diagnostics.
+    Location(Location),
 }
 
 impl<A: Architecture> Debug for ASTNode<A>
@@ -159,6 +285,12 @@ where
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             ASTNode::Instruction(inst, ops) => write!(f, "Instruction({inst:?}, {ops:?})"),
+            ASTNode::PseudoInstruction(name, ops) => {
+                write!(f, "PseudoInstruction({name:?}, {ops:?})")
+            }
+            ASTNode::Label(label) => write!(f, "Label({label:?})"),
+            ASTNode::Directive(directive) => write!(f, "Directive({directive:?})"),
+            ASTNode::Location(location) => write!(f, "Location({location})"),
         }
     }
 }
@@ -167,6 +299,87 @@ where
 pub enum PlausibleOperator<A: Architecture> {
     Symbol(A::Symbol),
     Value(isize),
+    /// A reference to a label or constant, replaced by its value during layout.
+    Identifier(String),
+    /// An expression referencing labels or constants, evaluated during layout.
+    Expression(Expression),
+    /// A string literal, only meaningful to directives.
+    String(String),
+}
+
+impl<A: Architecture> PlausibleOperator<A> {
+    fn from_tokens(tokens: &[ArchToken<A>]) -> Result<Self, Box<dyn Error>> {
+        Ok(match tokens {
+            [ArchToken::Symbol(symbol)] => PlausibleOperator::Symbol(symbol.clone()),
+            [ArchToken::String(string)] => PlausibleOperator::String(string.clone()),
+            // Section names look like directives
+            [ArchToken::Identifier(identifier) | ArchToken::Directive(identifier)] => {
+                PlausibleOperator::Identifier(identifier.clone())
+            }
+            tokens => {
+                let tokens = tokens
+                    .iter()
+                    .map(|token| match token {
+                        ArchToken::Value(value) => Ok(Token::Value(*value)),
+                        ArchToken::Identifier(identifier) | ArchToken::Directive(identifier) => {
+                            Ok(Token::Symbol(identifier.clone()))
+                        }
+                        ArchToken::Operator(operator) => Ok(Token::Operator(*operator)),
+                        _ => Err("Unexpected token in expression"),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                PlausibleOperator::from_expression(Expression::parse(&tokens)?)
+            }
+        })
+    }
+
+    /// Folds `expression` into a value if it's constant.
+    pub fn from_expression(expression: Expression) -> Self {
+        match (expression.evaluate_constant(), expression) {
+            (Some(value), _) => PlausibleOperator::Value(value),
+            (None, Expression::Identifier(identifier)) => PlausibleOperator::Identifier(identifier),
+            (None, expression) => PlausibleOperator::Expression(expression),
+        }
+    }
+
+    /// Replaces the references to `identifier` with `value`.
+    pub fn replace(&self, identifier: &str, value: &Expression) -> Self {
+        match self.to_expression() {
+            Some(expression) => Self::from_expression(expression.replace(identifier, value)),
+            None => self.clone(),
+        }
+    }
+
+    /// Replaces each identifier with the result of `f`.
+    pub fn map_identifiers(&self, f: &impl Fn(&str) -> Expression) -> Self {
+        match self.to_expression() {
+            Some(expression) => Self::from_expression(expression.map_identifiers(f)),
+            None => self.clone(),
+        }
+    }
+
+    /// Returns the operator as an expression, if it stands for a value.
+    pub fn to_expression(&self) -> Option<Expression> {
+        match self {
+            PlausibleOperator::Value(value) => Some(Expression::Value(*value)),
+            PlausibleOperator::Identifier(identifier) => {
+                Some(Expression::Identifier(identifier.clone()))
+            }
+            PlausibleOperator::Expression(expression) => Some(expression.clone()),
+            _ => None,
+        }
+    }
+
+    /// Stands in for operators that can't be known until layout, so that the
+    /// instruction overload (and thus its size) can be chosen beforehand.
+    pub fn or_placeholder(&self) -> Self {
+        match self {
+            PlausibleOperator::Identifier(_) | PlausibleOperator::Expression(_) => {
+                PlausibleOperator::Value(0)
+            }
+            operator => operator.clone(),
+        }
+    }
 }
 
 impl<A: Architecture> Debug for PlausibleOperator<A>
@@ -177,6 +390,9 @@ where
         match self {
             PlausibleOperator::Symbol(symbol) => write!(f, "Symbol({symbol:?})"),
             PlausibleOperator::Value(value) => write!(f, "Value({value:?})"),
+            PlausibleOperator::Identifier(identifier) => write!(f, "Identifier({identifier:?})"),
+            PlausibleOperator::Expression(expression) => write!(f, "Expression({expression})"),
+            PlausibleOperator::String(string) => write!(f, "String({string:?})"),
         }
     }
 }
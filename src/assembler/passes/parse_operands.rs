@@ -1,17 +1,26 @@
-use crate::arch_def::{Architecture, Instruction, OperandKind};
+use crate::arch_def::{Architecture, Extensions, Instruction, OperandKind};
 use crate::assembler::AssemblerPass;
+use crate::assembler::diagnostics::{Location, error};
+use crate::assembler::directives::Directive;
 use crate::assembler::passes::parse::{ASTNode, PlausibleOperator};
+use std::error::Error;
 use std::fmt::{Debug, Formatter};
 use std::marker::PhantomData;
 use std::rc::Rc;
 
 pub struct ParseOperandsPass<A: Architecture> {
+    location: Location,
+    /// Checked again here, for the instructions pseudo-instructions and
+    /// relaxation expand into
+    extensions: Extensions,
     phantom_architecture: PhantomData<A>,
 }
 
 impl<A: Architecture> Default for ParseOperandsPass<A> {
     fn default() -> Self {
         Self {
+            location: Location::default(),
+            extensions: Extensions::default(),
             phantom_architecture: PhantomData,
         }
     }
@@ -22,26 +31,51 @@ impl<A: Architecture> AssemblerPass for ParseOperandsPass<A> {
     type Output = ASTNodeOperandsParsed<A>;
 
     fn apply(&mut self, item: Self::Input) -> impl IntoIterator<Item = Self::Output> {
-        use std::iter::once;
-
         match item {
-            ASTNode::Instruction(inst, ops) => once(ASTNodeOperandsParsed::Instruction(
+            ASTNode::Instruction(inst, ops) => Some(ASTNodeOperandsParsed::Instruction(
                 inst,
-                parse_operands(inst, ops.as_ref()),
+                self.extensions
+                    .check::<A>(&inst)
+                    .and_then(|_| parse_operands(inst, ops.as_ref()))
+                    .unwrap_or_else(|message| error(&self.location, message)),
             )),
+            ASTNode::Directive(directive) => Some(ASTNodeOperandsParsed::Directive(directive)),
+            ASTNode::Label(_) => None,
+            ASTNode::PseudoInstruction(..) => {
+                unreachable!("Pseudo-instructions must be expanded beforehand")
+            }
+            ASTNode::Location(location) => {
+                self.location = location.clone();
+                Some(ASTNodeOperandsParsed::Location(location))
+            }
         }
     }
 }
 
-fn parse_operands<A: Architecture>(
+impl<A: Architecture> ParseOperandsPass<A> {
+    /// Only accepts the instructions of the given extensions.
+    pub fn with_extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions = extensions;
+        self
+    }
+}
+
+/// The parsed operands of an instruction.
+pub type Operands<A> = Rc<[<<A as Architecture>::OperandKind as OperandKind<A>>::Operand]>;
+
+pub(crate) fn parse_operands<A: Architecture>(
     instruction: A::Instruction,
     operands: &[PlausibleOperator<A>],
-) -> Rc<[<A::OperandKind as OperandKind<A>>::Operand]> {
+) -> Result<Operands<A>, Box<dyn Error>> {
     instruction
         .operands()
         .into_iter()
         .zip(operands.iter())
-        .map(|(kind, op)| kind.parse(op.clone()).unwrap())
+        .map(|(kind, op)| {
+            kind.parse(op.clone()).map_err(|error| {
+                format!("Invalid operands for {}: {error}", instruction.name()).into()
+            })
+        })
         .collect()
 }
 
@@ -50,6 +84,8 @@ pub enum ASTNodeOperandsParsed<A: Architecture> {
         A::Instruction,
         Rc<[<A::OperandKind as OperandKind<A>>::Operand]>,
     ),
+    Directive(Directive<A>),
+    Location(Location),
 }
 
 impl<A: Architecture> Debug for ASTNodeOperandsParsed<A>
@@ -63,6 +99,8 @@ where
             ASTNodeOperandsParsed::Instruction(inst, ops) => {
                 write!(f, "Instruction({inst:?}, {ops:?})")
             }
+            ASTNodeOperandsParsed::Directive(directive) => write!(f, "Directive({directive:?})"),
+            ASTNodeOperandsParsed::Location(location) => write!(f, "Location({location})"),
         }
     }
 }
@@ -0,0 +1,54 @@
+use crate::arch_def::{Architecture, Instruction};
+use crate::assembler::AssemblerPass;
+use crate::assembler::diagnostics::{Location, error};
+use crate::assembler::passes::parse::{ASTNode, accepts};
+use std::marker::PhantomData;
+
+/// Expands pseudo-instructions into the real instructions given by
+/// [`Architecture::expand_pseudo_instruction`], so that their size is known
+/// during layout.
+pub struct PseudoInstructionPass<A: Architecture> {
+    location: Location,
+    phantom_architecture: PhantomData<A>,
+}
+
+impl<A: Architecture> Default for PseudoInstructionPass<A> {
+    fn default() -> Self {
+        Self {
+            location: Location::default(),
+            phantom_architecture: PhantomData,
+        }
+    }
+}
+
+impl<A: Architecture> AssemblerPass for PseudoInstructionPass<A> {
+    type Input = ASTNode<A>;
+    type Output = ASTNode<A>;
+
+    fn apply(&mut self, item: Self::Input) -> impl IntoIterator<Item = Self::Output> {
+        match item {
+            ASTNode::PseudoInstruction(name, ops) => {
+                let expansion = A::expand_pseudo_instruction(&name, &ops)
+                    .unwrap_or_else(|message| error(&self.location, message));
+
+                expansion
+                    .into_iter()
+                    .map(|(inst, ops)| {
+                        if !accepts(inst, &ops) {
+                            error(
+                                &self.location,
+                                format!("{name} expands into an invalid {}", inst.name()),
+                            );
+                        }
+                        ASTNode::Instruction(inst, ops.into())
+                    })
+                    .collect()
+            }
+            ASTNode::Location(location) => {
+                self.location = location.clone();
+                vec![ASTNode::Location(location)]
+            }
+            node => vec![node],
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use crate::arch_def::{Architecture, Instruction};
+use crate::assembler::AssemblerPass;
+use crate::assembler::diagnostics::{Level, Location, error, report};
+use crate::assembler::expression::Expression;
+use crate::assembler::passes::layout::LayoutPass;
+use crate::assembler::passes::parse::{ASTNode, PlausibleOperator, accepts};
+use crate::assembler::passes::parse_operands::parse_operands;
+
+/// Labels placed before relaxable instructions, to find out their address.
+/// They can't clash with the labels in the source, which can't contain `@`.
+const MARKER_PREFIX: &str = "@relax";
+
+/// Replaces the instructions whose operands don't fit with the longer
+/// sequence given by [`Architecture::relax`].
+///
+/// Relaxing an instruction moves everything after it, which can push other
+/// instructions out of range, so the program is laid out again until no more
+/// instructions need to be relaxed. Instructions are never shrunk back, so
+/// this always converges. Only operands that refer to symbols are relaxed,
+/// literals that don't fit are reported while parsing.
+///
+/// The longer sequences may overwrite a scratch register, see
+/// [`RelaxationPass::with_scratch`]. Without one, each relaxed instruction is
+/// reported, as the architecture's default may hold a value.
+pub struct RelaxationPass<A: Architecture> {
+    nodes: Vec<ASTNode<A>>,
+    markers: usize,
+    scratch: Option<A::Symbol>,
+}
+
+impl<A: Architecture> Default for RelaxationPass<A> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            markers: 0,
+            scratch: None,
+        }
+    }
+}
+
+impl<A: Architecture> AssemblerPass for RelaxationPass<A> {
+    type Input = ASTNode<A>;
+    type Output = ASTNode<A>;
+
+    fn apply(&mut self, item: Self::Input) -> impl IntoIterator<Item = Self::Output> {
+        if let ASTNode::Instruction(inst, ops) = &item
+            && ops.iter().any(|op| {
+                matches!(
+                    op,
+                    PlausibleOperator::Identifier(_) | PlausibleOperator::Expression(_)
+                )
+            })
+            && A::relax(*inst, ops, self.scratch.clone()).is_some()
+        {
+            self.markers += 1;
+            self.nodes
+                .push(ASTNode::Label(format!("{MARKER_PREFIX}{}", self.markers)));
+        }
+        self.nodes.push(item);
+
+        vec![]
+    }
+
+    fn finish(&mut self) -> impl IntoIterator<Item = Self::Output> {
+        while self.relax_all() {}
+        std::mem::take(&mut self.nodes)
+    }
+}
+
+impl<A: Architecture> RelaxationPass<A> {
+    /// Picks the register the relaxed sequences may overwrite, instead of the
+    /// one the architecture uses by default.
+    pub fn with_scratch(mut self, scratch: A::Symbol) -> Self {
+        self.scratch = Some(scratch);
+        self
+    }
+
+    /// Lays the program out and relaxes the instructions that don't fit.
+    /// Returns whether any was relaxed.
+    fn relax_all(&mut self) -> bool {
+        let mut layout = LayoutPass::<A>::default();
+        let _ = Vec::from_iter(layout.apply_all_partial(self.nodes.clone()));
+
+        let mut relaxed = false;
+        let mut marker = None;
+        let mut location = Location::default();
+
+        let nodes = std::mem::take(&mut self.nodes);
+        for node in nodes {
+            match node {
+                ASTNode::Label(label) if label.starts_with(MARKER_PREFIX) => {
+                    marker = Some(label.clone());
+                    self.nodes.push(ASTNode::Label(label));
+                }
+                ASTNode::Instruction(inst, ops)
+                    if let Some(marker) = marker.take()
+                        && !fits(&layout, &marker, inst, &ops)
+                        && let Some(expansion) = A::relax(inst, &ops, self.scratch.clone()) =>
+                {
+                    if self.scratch.is_none() {
+                        report(
+                            Level::Warning,
+                            &location,
+                            format!(
+                                "Relaxing {} overwrites the default scratch register",
+                                inst.name()
+                            ),
+                        );
+                    }
+                    let address = Expression::Identifier(marker);
+                    for (inst, ops) in expansion {
+                        let ops = ops
+                            .iter()
+                            .map(|op| op.replace(".", &address))
+                            .collect::<Vec<_>>();
+                        if !accepts(inst, &ops) {
+                            error(
+                                &location,
+                                format!("Relaxation expands into an invalid {}", inst.name()),
+                            );
+                        }
+                        self.nodes.push(ASTNode::Instruction(inst, ops.into()));
+                    }
+                    relaxed = true;
+                }
+                ASTNode::Location(node_location) => {
+                    location = node_location.clone();
+                    self.nodes.push(ASTNode::Location(node_location));
+                }
+                node => self.nodes.push(node),
+            }
+        }
+
+        relaxed
+    }
+}
+
+/// Whether the operands of `instruction`, found at `marker`, fit once resolved.
+/// Operands that can't be resolved yet are left for the layout to report.
+fn fits<A: Architecture>(
+    layout: &LayoutPass<A>,
+    marker: &str,
+    instruction: A::Instruction,
+    operands: &[PlausibleOperator<A>],
+) -> bool {
+    let symbols = |symbol: &str| match symbol {
+        "." => layout.symbol(marker),
+        symbol => layout.symbol(symbol),
+    };
+
+    let resolved = operands
+        .iter()
+        .map(|operand| match operand.to_expression() {
+            Some(expression) => expression
+                .evaluate(&symbols)
+                .ok()
+                .map(PlausibleOperator::Value),
+            None => Some(operand.clone()),
+        })
+        .collect::<Option<Vec<_>>>();
+
+    match resolved {
+        Some(resolved) => parse_operands(instruction, &resolved).is_ok(),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch_def::registry::AssemblerOptions;
+    use crate::architectures;
+
+    fn assemble(source: &str, options: AssemblerOptions) -> Vec<u8> {
+        let registry = architectures::registry();
+        let sections = registry.get("sisa-i").unwrap().assemble(source, &options);
+        sections.unwrap().remove(0).bytes
+    }
+
+    #[test]
+    fn relaxes_far_branches_with_the_scratch_register() {
+        let options = AssemblerOptions {
+            relax_scratch: Some("sp".to_string()),
+            ..AssemblerOptions::default()
+        };
+        let bytes = assemble("bz r1, far\n.org 600\nfar: halt\n", options);
+
+        // bnz r1, 3; movi sp, far; movhi sp, far >> 8; jalr sp, sp
+        assert_eq!(bytes[..8], [0x03, 0x63, 0xb2, 0x5e, 0x04, 0x5f, 0xc0, 0xaf]);
+    }
+
+    #[test]
+    fn leaves_near_branches_alone() {
+        let options = AssemblerOptions {
+            relax: true,
+            ..AssemblerOptions::default()
+        };
+        assert_eq!(assemble("bz r1, near\nnear: halt\n", options).len(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "200 doesn't fit in a signed 8-bit immediate")]
+    fn reports_literal_targets_out_of_range() {
+        let options = AssemblerOptions {
+            relax: true,
+            ..AssemblerOptions::default()
+        };
+        assemble("bz r1, 200\n", options);
+    }
+}
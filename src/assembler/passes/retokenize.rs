@@ -1,15 +1,19 @@
-use crate::arch_def::{Architecture, Instruction, Symbol};
+use crate::arch_def::index::InstructionIndex;
+use crate::arch_def::{Architecture, CaseSensitivity, Symbol};
 use crate::assembler::AssemblerPass;
-use crate::assembler::passes::tokenize::Token;
+use crate::assembler::diagnostics::Location;
+use crate::assembler::passes::tokenize::{Operator, Token};
 use std::marker::PhantomData;
 
 pub struct RetokenizePass<A: Architecture> {
+    case_sensitivity: CaseSensitivity,
     phantom_architecture: PhantomData<A>,
 }
 
 impl<A: Architecture> Default for RetokenizePass<A> {
     fn default() -> Self {
         Self {
+            case_sensitivity: A::case_sensitivity(),
             phantom_architecture: PhantomData,
         }
     }
@@ -23,35 +27,65 @@ impl<A: Architecture> AssemblerPass for RetokenizePass<A> {
         use std::iter::once;
 
         match item {
-            Token::Symbol(symbol) => once(Self::parse_symbol(symbol)),
+            Token::Symbol(symbol) => once(self.parse_symbol(symbol)),
             Token::Value(value) => once(ArchToken::Value(value)),
+            Token::String(string) => once(ArchToken::String(string)),
+            Token::Operator(operator) => once(ArchToken::Operator(operator)),
             Token::Comma => once(ArchToken::Comma),
+            Token::Colon => once(ArchToken::Colon),
+            Token::Equals => once(ArchToken::Equals),
             Token::LineFeed => once(ArchToken::LineFeed),
+            Token::Location(location) => once(ArchToken::Location(location)),
         }
     }
 }
 
 impl<A: Architecture> RetokenizePass<A> {
-    fn parse_symbol(symbol: String) -> ArchToken<A> {
-        A::Instruction::enumerate()
-            .into_iter()
-            .find(|inst| inst.name() == symbol)
-            .map(|inst| inst.name().to_string())
-            .map(ArchToken::Instruction)
-            .unwrap_or_else(|| {
-                ArchToken::Symbol(
-                    Symbol::parse(&symbol)
-                        .unwrap_or_else(|_| panic!("Unparsable symbol: {}", symbol)),
-                )
+    /// Overrides the case sensitivity of the architecture.
+    pub fn with_case_sensitivity(mut self, case_sensitivity: CaseSensitivity) -> Self {
+        self.case_sensitivity = case_sensitivity;
+        self
+    }
+
+    fn parse_symbol(&self, symbol: String) -> ArchToken<A> {
+        if symbol.starts_with('.') {
+            // Directives share their syntax with scoped labels
+            return match self.case_sensitivity {
+                CaseSensitivity::Insensitive => ArchToken::Directive(symbol.to_ascii_lowercase()),
+                _ => ArchToken::Directive(symbol),
+            };
+        }
+
+        let ignore_case = self.case_sensitivity != CaseSensitivity::Sensitive;
+        InstructionIndex::<A>::get()
+            .mnemonic(&symbol, ignore_case)
+            .map(|name| ArchToken::Instruction(name.to_string()))
+            .or_else(|| Symbol::parse(&symbol).ok().map(ArchToken::Symbol))
+            .or_else(|| {
+                ignore_case
+                    .then(|| Symbol::parse(&symbol.to_ascii_lowercase()).ok())
+                    .flatten()
+                    .map(ArchToken::Symbol)
+            })
+            .unwrap_or_else(|| match self.case_sensitivity {
+                CaseSensitivity::Insensitive => ArchToken::Identifier(symbol.to_ascii_lowercase()),
+                _ => ArchToken::Identifier(symbol),
             })
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum ArchToken<A: Architecture> {
     Instruction(String),
+    Directive(String),
     Symbol(A::Symbol),
+    Identifier(String),
     Value(isize),
+    String(String),
+    Operator(Operator),
     Comma,
+    Colon,
+    Equals,
     LineFeed,
+    Location(Location),
 }
@@ -0,0 +1,278 @@
+use crate::arch_def::Architecture;
+use crate::assembler::AssemblerPass;
+use crate::assembler::diagnostics::{Location, error};
+use crate::assembler::directives::Directive;
+use crate::assembler::expression::Expression;
+use crate::assembler::passes::local_labels::is_local_label_name;
+use crate::assembler::passes::parse::{ASTNode, PlausibleOperator};
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// Qualifies labels and constants with the scope they're defined in.
+///
+/// ```text
+/// loop:
+///     .done:
+/// .scope lib
+///     print:
+///     .loop:
+/// .endscope
+/// ```
+///
+/// This defines `loop`, `loop.done`, `lib.print` and `lib.print.loop`. A
+/// scope is anonymous if its name is omitted.
+///
+/// Labels starting with a dot attach to the last global label. References
+/// are looked up from the innermost scope outwards, so `print` refers to
+/// `lib.print` inside the scope, and to a global `print` outside of it.
+///
+/// References may come before the definition, so the whole program is
+/// buffered and only emitted once finished.
+pub struct ScopePass<A: Architecture> {
+    /// The qualified name of each open scope, innermost last
+    scopes: Rc<[String]>,
+    /// The last global label of each open scope, and outside of them
+    globals: Vec<Option<String>>,
+    anonymous_scopes: usize,
+    definitions: HashSet<String>,
+    nodes: Vec<(ASTNode<A>, Context)>,
+    location: Location,
+}
+
+/// The scopes and global label a node is found in.
+struct Context {
+    scopes: Rc<[String]>,
+    global: Option<String>,
+}
+
+impl<A: Architecture> Default for ScopePass<A> {
+    fn default() -> Self {
+        Self {
+            scopes: Rc::new([]),
+            globals: vec![None],
+            anonymous_scopes: 0,
+            definitions: HashSet::new(),
+            nodes: vec![],
+            location: Location::default(),
+        }
+    }
+}
+
+impl<A: Architecture> AssemblerPass for ScopePass<A> {
+    type Input = ASTNode<A>;
+    type Output = ASTNode<A>;
+
+    fn apply(&mut self, item: Self::Input) -> impl IntoIterator<Item = Self::Output> {
+        let node = match item {
+            ASTNode::Label(label) if is_local_label_name(&label) => ASTNode::Label(label),
+            ASTNode::Label(label) => {
+                let qualified = self.define(&label);
+                if !label.starts_with('.') {
+                    *self.globals.last_mut().unwrap() = Some(qualified.clone());
+                }
+                ASTNode::Label(qualified)
+            }
+            ASTNode::Directive(Directive::Equ { name, value }) => {
+                ASTNode::Directive(Directive::Equ {
+                    name: self.define(&name),
+                    value,
+                })
+            }
+            ASTNode::Directive(Directive::Scope { name }) => {
+                let name = name.unwrap_or_else(|| {
+                    self.anonymous_scopes += 1;
+                    format!("@scope{}", self.anonymous_scopes)
+                });
+                let mut scopes = self.scopes.to_vec();
+                scopes.push(self.qualify(&name));
+                self.scopes = scopes.into();
+                self.globals.push(None);
+                return vec![];
+            }
+            ASTNode::Directive(Directive::EndScope) => {
+                if self.scopes.is_empty() {
+                    error(&self.location, "Unexpected .endscope");
+                }
+                self.scopes = self.scopes[..self.scopes.len() - 1].into();
+                self.globals.pop();
+                return vec![];
+            }
+            ASTNode::Location(location) => {
+                self.location = location.clone();
+                ASTNode::Location(location)
+            }
+            node => node,
+        };
+
+        let context = Context {
+            scopes: self.scopes.clone(),
+            global: self.globals.last().unwrap().clone(),
+        };
+        self.nodes.push((node, context));
+        vec![]
+    }
+
+    fn finish(&mut self) -> impl IntoIterator<Item = Self::Output> {
+        if !self.scopes.is_empty() {
+            error(&self.location, "Missing .endscope");
+        }
+
+        let nodes = std::mem::take(&mut self.nodes);
+        nodes
+            .into_iter()
+            .map(|(node, context)| {
+                let resolve = |operators: &[PlausibleOperator<A>]| {
+                    operators
+                        .iter()
+                        .map(|operator| {
+                            operator.map_identifiers(&|name| {
+                                Expression::Identifier(self.resolve(name, &context))
+                            })
+                        })
+                        .collect::<Rc<[_]>>()
+                };
+
+                match node {
+                    ASTNode::Instruction(inst, ops) => ASTNode::Instruction(inst, resolve(&ops)),
+                    ASTNode::PseudoInstruction(name, ops) => {
+                        ASTNode::PseudoInstruction(name, resolve(&ops))
+                    }
+                    ASTNode::Directive(Directive::Equ { name, value }) => {
+                        ASTNode::Directive(Directive::Equ {
+                            name,
+                            value: resolve(&[value])[0].clone(),
+                        })
+                    }
+                    ASTNode::Directive(Directive::Data { width, values }) => {
+                        ASTNode::Directive(Directive::Data {
+                            width,
+                            values: resolve(&values),
+                        })
+                    }
+                    ASTNode::Directive(Directive::Deferred { name, arguments }) => {
+                        // The section name is an identifier, but not a symbol
+                        let (kept, values) = arguments.split_at(usize::from(name == ".section"));
+                        ASTNode::Directive(Directive::Deferred {
+                            arguments: [kept, &resolve(values)].concat().into(),
+                            name,
+                        })
+                    }
+                    ASTNode::Directive(Directive::Report {
+                        level,
+                        condition,
+                        message,
+                    }) => ASTNode::Directive(Directive::Report {
+                        level,
+                        condition: condition.map(|condition| resolve(&[condition])[0].clone()),
+                        message: resolve(&message),
+                    }),
+                    ASTNode::Location(location) => {
+                        self.location = location.clone();
+                        ASTNode::Location(location)
+                    }
+                    node => node,
+                }
+            })
+            .collect::<Vec<_>>()
+    }
+}
+
+impl<A: Architecture> ScopePass<A> {
+    /// Returns the qualified name of a definition in the current scope.
+    fn qualify(&self, name: &str) -> String {
+        if name.starts_with('.') {
+            match self.globals.last().unwrap() {
+                Some(global) => format!("{global}{name}"),
+                None => error(
+                    &self.location,
+                    format!("Scoped label {name} has no global label to attach to"),
+                ),
+            }
+        } else {
+            match self.scopes.last() {
+                Some(scope) => format!("{scope}.{name}"),
+                None => name.to_string(),
+            }
+        }
+    }
+
+    fn define(&mut self, name: &str) -> String {
+        let name = self.qualify(name);
+        self.definitions.insert(name.clone());
+        name
+    }
+
+    /// Finds the definition a reference to `name` stands for.
+    fn resolve(&self, name: &str, context: &Context) -> String {
+        if name == "." {
+            return name.to_string();
+        }
+        if name.starts_with('.') {
+            return match &context.global {
+                Some(global) => format!("{global}{name}"),
+                None => error(
+                    &self.location,
+                    format!("Scoped label {name} has no global label to attach to"),
+                ),
+            };
+        }
+
+        context
+            .scopes
+            .iter()
+            .rev()
+            .map(|scope| format!("{scope}.{name}"))
+            .find(|name| self.definitions.contains(name))
+            .unwrap_or_else(|| name.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::architectures::test_arch::TestArch;
+    use crate::assembler::{AssemblerPasses, assemble};
+
+    fn bytes(source: &str) -> Vec<u8> {
+        assemble(AssemblerPasses::<TestArch>::default(), source)
+            .remove(0)
+            .bytes
+    }
+
+    #[test]
+    fn resolves_references_from_the_innermost_scope() {
+        // Each jump takes 5 bytes
+        let source = "
+            loop: jump .next
+            .next: jump loop
+            .scope lib
+            print: jump print
+            .loop: jump .loop
+            .endscope
+            jump lib.print
+            jump lib.print.loop
+            jump loop.next
+            .scope
+            loop: jump loop
+            .endscope
+            jump loop
+        ";
+        let expected = "
+            jump 5
+            jump 0
+            jump 10
+            jump 15
+            jump 10
+            jump 15
+            jump 5
+            jump 35
+            jump 0
+        ";
+        assert_eq!(bytes(source), bytes(expected));
+    }
+
+    #[test]
+    #[should_panic(expected = "Scoped label .next has no global label to attach to")]
+    fn rejects_scoped_labels_without_a_global_label() {
+        bytes(".next: halt");
+    }
+}
@@ -0,0 +1,221 @@
+use crate::arch_def::Architecture;
+use crate::assembler::diagnostics::{Location, error};
+use crate::assembler::directives::data_width;
+use crate::assembler::expression::Expression;
+use crate::assembler::passes::lines::LinePass;
+use crate::assembler::passes::tokenize::{Operator, Token, labels, location, statement};
+use std::error::Error;
+use std::marker::PhantomData;
+
+/// Defines constants for the layout of records.
+///
+/// ```text
+/// .struct Point
+/// x:  .word
+/// y:  .word
+/// tag: .byte 4
+/// .endstruct
+/// ```
+///
+/// With 2-byte words, this defines `Point.x` as 0, `Point.y` as 2, `Point.tag`
+/// as 4 and `Point` as 8. Each field is given the offset of its label, and the
+/// struct name is given the total size, both in [`Architecture::address_unit`]s
+/// like labels, so bytes on byte-addressed architectures. Fields are sized with
+/// `.byte` and `.word`, followed by an optional count, and must take whole
+/// address units. Room for a nested record is reserved with the directive as
+/// wide as an address unit, like `.byte Point`. The definition is replaced by
+/// the equivalent `.equ` lines.
+pub struct StructPass<A: Architecture> {
+    line: Vec<Token>,
+    location: Location,
+    definition: Option<StructDefinition>,
+    phantom_architecture: PhantomData<A>,
+}
+
+struct StructDefinition {
+    name: String,
+    /// The offset of the next field, as the tokens of an expression
+    offset: Vec<Token>,
+    location: Location,
+}
+
+impl<A: Architecture> Default for StructPass<A> {
+    fn default() -> Self {
+        Self {
+            line: vec![],
+            location: Location::default(),
+            definition: None,
+            phantom_architecture: PhantomData,
+        }
+    }
+}
+
+impl<A: Architecture> LinePass for StructPass<A> {
+    fn line(&mut self) -> &mut Vec<Token> {
+        &mut self.line
+    }
+
+    fn process_line(&mut self, line: Vec<Token>) -> Vec<Token> {
+        if let Some(location) = location(&line) {
+            self.location = location.clone();
+        }
+
+        let Some(definition) = &mut self.definition else {
+            return match statement(&line) {
+                [Token::Symbol(directive), Token::Symbol(name)] if directive == ".struct" => {
+                    self.definition = Some(StructDefinition {
+                        name: name.clone(),
+                        offset: vec![Token::Value(0)],
+                        location: self.location.clone(),
+                    });
+                    line[..line.len() - 2].to_vec()
+                }
+                [Token::Symbol(directive), ..] if directive == ".struct" => {
+                    error(&self.location, "Directive .struct expects a name")
+                }
+                [Token::Symbol(directive), ..] if directive == ".endstruct" => {
+                    error(&self.location, "Unexpected .endstruct")
+                }
+                _ => line,
+            };
+        };
+
+        let mut output = Vec::from_iter(location(&line).cloned().map(Token::Location));
+        for label in labels(&line) {
+            output.extend(equ(
+                &format!("{}.{label}", definition.name),
+                &definition.offset,
+            ));
+        }
+
+        match statement(&line) {
+            [] => {}
+            [Token::Symbol(directive)] if directive == ".endstruct" => {
+                output.extend(equ(&definition.name, &definition.offset));
+                self.definition = None;
+            }
+            [Token::Symbol(directive), count @ ..] if data_width::<A>(directive).is_some() => {
+                let width = data_width::<A>(directive).unwrap();
+                let size = match count {
+                    [] => vec![Token::Value(width as isize)],
+                    count => [
+                        &[
+                            Token::Value(width as isize),
+                            Token::Operator(Operator::Multiply),
+                            Token::Operator(Operator::OpenParenthesis),
+                        ],
+                        count,
+                        &[Token::Operator(Operator::CloseParenthesis)],
+                    ]
+                    .concat(),
+                };
+                definition.offset = units::<A>(&size)
+                    .and_then(|size| add(&definition.offset, &size))
+                    .unwrap_or_else(|message| error(&self.location, message));
+            }
+            _ => error(
+                &self.location,
+                format!("Only fields can be defined in .struct {}", definition.name),
+            ),
+        }
+
+        output
+    }
+
+    fn finish_lines(&mut self) {
+        if let Some(definition) = &self.definition {
+            error(
+                &definition.location,
+                format!("Missing .endstruct for {}", definition.name),
+            );
+        }
+    }
+}
+
+/// Returns the tokens of a size in bytes converted to address units, folded
+/// into a value if it's constant.
+fn units<A: Architecture>(bytes: &[Token]) -> Result<Vec<Token>, Box<dyn Error>> {
+    let unit = A::address_unit() as isize;
+    if unit == 1 {
+        return Ok(bytes.to_vec());
+    }
+
+    Ok(match Expression::parse(bytes)?.evaluate_constant() {
+        Some(bytes) if bytes % unit != 0 => {
+            return Err(
+                format!("A field of {bytes} bytes doesn't fill whole address units").into(),
+            );
+        }
+        Some(bytes) => vec![Token::Value(bytes / unit)],
+        None => [
+            &[Token::Operator(Operator::OpenParenthesis)],
+            bytes,
+            &[
+                Token::Operator(Operator::CloseParenthesis),
+                Token::Operator(Operator::Divide),
+                Token::Value(unit),
+            ],
+        ]
+        .concat(),
+    })
+}
+
+/// Returns the tokens of `left + right`, folded into a value if it's constant.
+fn add(left: &[Token], right: &[Token]) -> Result<Vec<Token>, Box<dyn Error>> {
+    let sum = [left, &[Token::Operator(Operator::Plus)], right].concat();
+    Ok(match Expression::parse(&sum)?.evaluate_constant() {
+        Some(value) => vec![Token::Value(value)],
+        None => sum,
+    })
+}
+
+/// Returns the line `.equ name, value`.
+fn equ(name: &str, value: &[Token]) -> Vec<Token> {
+    [
+        &[
+            Token::Symbol(".equ".to_string()),
+            Token::Symbol(name.to_string()),
+            Token::Comma,
+        ],
+        value,
+        &[Token::LineFeed],
+    ]
+    .concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::architectures::test_arch::TestArch;
+    use crate::assembler::passes::layout::tests::Wide;
+    use crate::assembler::{AssemblerPasses, assemble};
+
+    const POINT: &str = "
+        .struct Point
+        x:  .word
+        y:  .word
+        tag: .byte 4
+        .endstruct
+        .byte Point.x, Point.y, Point.tag, Point
+    ";
+
+    #[test]
+    fn gives_fields_their_offset() {
+        let sections = assemble(AssemblerPasses::<TestArch>::default(), POINT);
+        assert_eq!(sections[0].bytes, [0, 2, 4, 8]);
+    }
+
+    #[test]
+    fn counts_offsets_in_address_units() {
+        let sections = assemble(AssemblerPasses::<Wide>::default(), POINT);
+        assert_eq!(sections[0].bytes, [0, 1, 2, 4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "A field of 1 bytes doesn't fill whole address units")]
+    fn rejects_fields_smaller_than_an_address_unit() {
+        assemble(
+            AssemblerPasses::<Wide>::default(),
+            ".struct Flag\nset: .byte\n.endstruct",
+        );
+    }
+}
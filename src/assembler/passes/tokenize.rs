@@ -1,8 +1,39 @@
 use crate::assembler::AssemblerPass;
+use crate::assembler::diagnostics::{Location, error};
+use crate::assembler::passes::local_labels::parse_local_label_reference;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
 
-#[derive(Default)]
+/// Splits the source code into tokens. Each line starts with a
+/// [`Token::Location`] marker, so later passes can point at it.
 pub struct TokenizePass {
     state: TokenizerState,
+    location: Location,
+    at_line_start: bool,
+}
+
+impl Default for TokenizePass {
+    fn default() -> Self {
+        Self::new(Location::DEFAULT_FILE.into())
+    }
+}
+
+impl TokenizePass {
+    pub fn new(file: Rc<str>) -> Self {
+        Self {
+            state: TokenizerState::default(),
+            location: Location::new(file, 1),
+            at_line_start: true,
+        }
+    }
+
+    fn locate(&mut self, mut tokens: Vec<Token>) -> Vec<Token> {
+        if self.at_line_start && !tokens.is_empty() {
+            tokens.insert(0, Token::Location(self.location.clone()));
+            self.at_line_start = false;
+        }
+        tokens
+    }
 }
 
 impl AssemblerPass for TokenizePass {
@@ -10,7 +41,34 @@ impl AssemblerPass for TokenizePass {
     type Output = Token;
 
     fn apply(&mut self, item: Self::Input) -> impl IntoIterator<Item = Self::Output> {
-        let (next_state, output) = match (&self.state, item) {
+        let mut output = vec![];
+
+        // Operators end as soon as the next character can't extend them
+        if let TokenizerState::InOperator(operator) = &self.state
+            && !is_operator(&format!("{operator}{item}"))
+        {
+            output.extend(self.state.finish(&self.location));
+            self.state = TokenizerState::Initial;
+        }
+
+        let (next_state, tokens) = match (&self.state, item) {
+            // Tokenize string
+            (TokenizerState::InString(s), '"') => {
+                (TokenizerState::Initial, vec![Token::String(s.clone())])
+            }
+            (TokenizerState::InString(s), '\\') => {
+                (TokenizerState::InStringEscape(s.clone()), vec![])
+            }
+            (TokenizerState::InString(_), '\n') => error(&self.location, "Unterminated string"),
+            (TokenizerState::InString(s), c) => (
+                TokenizerState::InString(s.clone() + &String::from(c)),
+                vec![],
+            ),
+            (TokenizerState::InStringEscape(s), c) => (
+                TokenizerState::InString(s.clone() + &String::from(unescape(c))),
+                vec![],
+            ),
+
             // Linefeed (or semicolon)
             (TokenizerState::Initial, '\n') => (TokenizerState::Initial, vec![Token::LineFeed]),
             (TokenizerState::Initial, ';') => (TokenizerState::Initial, vec![Token::LineFeed]),
@@ -21,48 +79,81 @@ impl AssemblerPass for TokenizePass {
             // Finish tokens on whitespace or commas
             (state, '\n') => (
                 TokenizerState::Initial,
-                vec![state.finish_or_error(), Token::LineFeed],
+                vec![state.finish_or_error(&self.location), Token::LineFeed],
             ),
             (state, ';') => (
                 TokenizerState::Initial,
-                vec![state.finish_or_error(), Token::LineFeed],
+                vec![state.finish_or_error(&self.location), Token::LineFeed],
             ),
-            (state, c) if c.is_whitespace() => {
-                (TokenizerState::Initial, vec![state.finish_or_error()])
-            }
+            (state, c) if c.is_whitespace() => (
+                TokenizerState::Initial,
+                vec![state.finish_or_error(&self.location)],
+            ),
+            (TokenizerState::Initial, ',') => (TokenizerState::Initial, vec![Token::Comma]),
             (state, ',') => (
                 TokenizerState::Initial,
-                vec![state.finish_or_error(), Token::Comma],
+                vec![state.finish_or_error(&self.location), Token::Comma],
+            ),
+            (TokenizerState::Initial, ':') => (TokenizerState::Initial, vec![Token::Colon]),
+            (state, ':') => (
+                TokenizerState::Initial,
+                vec![state.finish_or_error(&self.location), Token::Colon],
             ),
 
+            (TokenizerState::Initial, '"') => (TokenizerState::InString(String::new()), vec![]),
+
             // Tokenize symbol
-            (TokenizerState::Initial, c) if c.is_alphabetic() => {
+            (TokenizerState::Initial, c)
+                if c.is_alphabetic() || c == '_' || c == '.' || c == '\\' =>
+            {
                 (TokenizerState::InSymbol(String::from(c)), vec![])
             }
-            (TokenizerState::InSymbol(s), c) if c.is_alphanumeric() => (
+            (TokenizerState::InSymbol(s), c) if c.is_alphanumeric() || c == '_' || c == '.' => (
                 TokenizerState::InSymbol(s.clone() + &String::from(c)),
                 vec![],
             ),
 
             // Tokenize value
-            (TokenizerState::Initial, c) if c.is_ascii_digit() || c == '-' => {
+            (TokenizerState::Initial, c) if c.is_ascii_digit() => {
                 (TokenizerState::InValue(String::from(c)), vec![])
             }
-            (TokenizerState::InValue(s), c) if c.is_ascii_digit() => (
+            (TokenizerState::InValue(s), c) if c.is_ascii_alphanumeric() => (
                 TokenizerState::InValue(s.clone() + &String::from(c)),
                 vec![],
             ),
 
+            // Tokenize operator
+            (TokenizerState::InOperator(s), c) => (
+                TokenizerState::InOperator(s.clone() + &String::from(c)),
+                vec![],
+            ),
+            (TokenizerState::Initial, c) if is_operator(&String::from(c)) => {
+                (TokenizerState::InOperator(String::from(c)), vec![])
+            }
+            (state, c) if is_operator(&String::from(c)) => (
+                TokenizerState::InOperator(String::from(c)),
+                vec![state.finish_or_error(&self.location)],
+            ),
+
             // Fail for anything else
-            _ => panic!("Unexpected token"),
+            (_, c) => error(&self.location, format!("Unexpected character {c:?}")),
         };
 
         self.state = next_state;
+        output.extend(tokens);
+
+        let output = self.locate(output);
+        if item == '\n' {
+            self.location.line += 1;
+            self.at_line_start = true;
+        }
         output
     }
 
     fn finish(&mut self) -> impl IntoIterator<Item = Self::Output> {
-        self.state.finish()
+        let output = Vec::from_iter(self.state.finish(&self.location));
+        self.state = TokenizerState::Initial;
+        self.locate(output)
     }
 }
 
@@ -72,26 +163,216 @@ enum TokenizerState {
     Initial,
     InSymbol(String),
     InValue(String),
+    InOperator(String),
+    InString(String),
+    InStringEscape(String),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Token {
     Symbol(String),
     Value(isize),
+    String(String),
+    Operator(Operator),
     Comma,
+    Colon,
+    Equals,
     LineFeed,
+    /// Marks the location of the following tokens.
+    Location(Location),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Remainder,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+    Not,
+    And,
+    Or,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    OpenParenthesis,
+    CloseParenthesis,
+}
+
+impl Operator {
+    pub fn parse(operator: &str) -> Option<Self> {
+        Some(match operator {
+            "+" => Self::Plus,
+            "-" => Self::Minus,
+            "*" => Self::Multiply,
+            "/" => Self::Divide,
+            "%" => Self::Remainder,
+            "&" => Self::BitAnd,
+            "|" => Self::BitOr,
+            "^" => Self::BitXor,
+            "~" => Self::BitNot,
+            "<<" => Self::ShiftLeft,
+            ">>" => Self::ShiftRight,
+            "!" => Self::Not,
+            "&&" => Self::And,
+            "||" => Self::Or,
+            "==" => Self::Equal,
+            "!=" => Self::NotEqual,
+            "<" => Self::Less,
+            "<=" => Self::LessEqual,
+            ">" => Self::Greater,
+            ">=" => Self::GreaterEqual,
+            "(" => Self::OpenParenthesis,
+            ")" => Self::CloseParenthesis,
+            _ => return None,
+        })
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::Remainder => "%",
+            Self::BitAnd => "&",
+            Self::BitOr => "|",
+            Self::BitXor => "^",
+            Self::BitNot => "~",
+            Self::ShiftLeft => "<<",
+            Self::ShiftRight => ">>",
+            Self::Not => "!",
+            Self::And => "&&",
+            Self::Or => "||",
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::Less => "<",
+            Self::LessEqual => "<=",
+            Self::Greater => ">",
+            Self::GreaterEqual => ">=",
+            Self::OpenParenthesis => "(",
+            Self::CloseParenthesis => ")",
+        })
+    }
 }
 
 impl TokenizerState {
-    fn finish(&self) -> Option<Token> {
+    fn finish(&self, location: &Location) -> Option<Token> {
         match self {
             Self::InSymbol(symbol) => Some(Token::Symbol(symbol.clone())),
-            Self::InValue(value) => Some(Token::Value(value.parse().unwrap())),
+            Self::InValue(value) => Some(match parse_value(value) {
+                Some(value) => Token::Value(value),
+                // References to local labels, like `1b` or `1f`
+                None if parse_local_label_reference(value).is_some() => {
+                    Token::Symbol(value.clone())
+                }
+                None => error(location, format!("Invalid value: {value}")),
+            }),
+            Self::InOperator(operator) if operator == "=" => Some(Token::Equals),
+            Self::InOperator(operator) => Some(Token::Operator(
+                Operator::parse(operator)
+                    .unwrap_or_else(|| error(location, format!("Invalid operator: {operator}"))),
+            )),
+            Self::InString(_) | Self::InStringEscape(_) => error(location, "Unterminated string"),
             _ => None,
         }
     }
 
-    fn finish_or_error(&self) -> Token {
-        self.finish().unwrap_or_else(|| panic!("Unexpected token"))
+    fn finish_or_error(&self, location: &Location) -> Token {
+        self.finish(location)
+            .unwrap_or_else(|| error(location, "Unexpected token"))
+    }
+}
+
+/// Skips over the location marker and labels at the start of a line of tokens.
+pub(crate) fn statement(line: &[Token]) -> &[Token] {
+    match line {
+        [Token::Location(_), rest @ ..] => statement(rest),
+        [Token::Symbol(_), Token::Colon, rest @ ..] => statement(rest),
+        rest => rest,
+    }
+}
+
+/// Returns the labels defined at the start of a line of tokens.
+pub(crate) fn labels(line: &[Token]) -> Vec<&String> {
+    match line {
+        [Token::Location(_), rest @ ..] => labels(rest),
+        [Token::Symbol(label), Token::Colon, rest @ ..] => {
+            let mut labels = labels(rest);
+            labels.insert(0, label);
+            labels
+        }
+        _ => vec![],
+    }
+}
+
+/// Returns the location marker of a line of tokens, if any.
+pub(crate) fn location(line: &[Token]) -> Option<&Location> {
+    match line {
+        [Token::Location(location), ..] => Some(location),
+        _ => None,
+    }
+}
+
+/// Parses a decimal, hexadecimal (`0x`) or binary (`0b`) value.
+pub(crate) fn parse_value(value: &str) -> Option<isize> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        isize::from_str_radix(hex, 16)
+    } else if let Some(bin) = value.strip_prefix("0b") {
+        isize::from_str_radix(bin, 2)
+    } else {
+        value.parse()
+    }
+    .ok()
+}
+
+fn is_operator(operator: &str) -> bool {
+    operator == "=" || Operator::parse(operator).is_some()
+}
+
+fn unescape(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        '0' => '\0',
+        c => c,
+    }
+}
+
+/// The tokens of `source` without location markers or blank lines, to compare
+/// the output of the passes in tests.
+#[cfg(test)]
+pub(crate) fn tokenize(source: &str) -> Vec<Token> {
+    normalize(TokenizePass::default().apply_all(source.chars()))
+}
+
+/// Drops location markers and blank lines, see [`tokenize`].
+#[cfg(test)]
+pub(crate) fn normalize(tokens: impl IntoIterator<Item = Token>) -> Vec<Token> {
+    let mut normalized = vec![];
+    for token in tokens {
+        match token {
+            Token::Location(_) => {}
+            Token::LineFeed if matches!(normalized.last(), None | Some(Token::LineFeed)) => {}
+            token => normalized.push(token),
+        }
+    }
+    if normalized
+        .last()
+        .is_some_and(|token| *token != Token::LineFeed)
+    {
+        normalized.push(Token::LineFeed);
     }
+    normalized
 }
@@ -0,0 +1,74 @@
+use crate::arch_def::{Architecture, Instruction};
+use crate::assembler::AssemblerPass;
+use crate::assembler::diagnostics::{Location, error};
+use crate::assembler::passes::parse_operands::ASTNodeOperandsParsed;
+use std::marker::PhantomData;
+
+/// Rejects instructions whose operands break the constraints of their
+/// encoding, see [`Instruction::validate`], before they're emitted. The
+/// overload was already picked while parsing, so it's never reconsidered.
+pub struct ValidatePass<A: Architecture> {
+    location: Location,
+    phantom_architecture: PhantomData<A>,
+}
+
+impl<A: Architecture> Default for ValidatePass<A> {
+    fn default() -> Self {
+        Self {
+            location: Location::default(),
+            phantom_architecture: PhantomData,
+        }
+    }
+}
+
+impl<A: Architecture> AssemblerPass for ValidatePass<A> {
+    type Input = ASTNodeOperandsParsed<A>;
+    type Output = ASTNodeOperandsParsed<A>;
+
+    fn apply(&mut self, item: Self::Input) -> impl IntoIterator<Item = Self::Output> {
+        match &item {
+            ASTNodeOperandsParsed::Instruction(inst, ops) => {
+                if let Err(message) = inst.validate(ops) {
+                    error(
+                        &self.location,
+                        format!("Invalid operands for {}: {message}", inst.name()),
+                    );
+                }
+            }
+            ASTNodeOperandsParsed::Location(location) => self.location = location.clone(),
+            ASTNodeOperandsParsed::Directive(_) => {}
+        }
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assembler::{AssemblerPasses, assemble};
+    use crate::define_architecture;
+
+    define_architecture! {
+        architecture Pairs {}
+        symbols PairsSymbol {
+            Reg = "r" 0..8,
+        }
+        operand_kinds PairsOperandKind {
+            Reg = symbol Reg,
+        }
+        instructions PairsInstruction: u16 {
+            Swap = "swap" (Reg ra, Reg rb) => [op[15:12] = 9, ra[11:9], rb[8:6]] if ra != rb,
+        }
+    }
+
+    #[test]
+    fn accepts_operands_that_meet_the_constraint() {
+        let sections = assemble(AssemblerPasses::<Pairs>::default(), "swap r1, r2\n");
+        assert_eq!(sections[0].bytes, [0x80, 0x92]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid operands for swap: Expected ra != rb")]
+    fn rejects_operands_that_break_the_constraint() {
+        assemble(AssemblerPasses::<Pairs>::default(), "swap r1, r1\n");
+    }
+}
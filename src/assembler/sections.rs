@@ -0,0 +1,128 @@
+use crate::arch_def::Architecture;
+use std::error::Error;
+use std::fmt::Write;
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SectionFlags {
+    pub writable: bool,
+    pub executable: bool,
+    /// The section only reserves space (like `.bss`), so it can't hold initialized data.
+    pub uninitialized: bool,
+}
+
+impl SectionFlags {
+    /// Parses a flag string as given to `.section`: `w` (writable), `x` (executable)
+    /// and `u` (uninitialized). `a` (allocatable) is accepted for compatibility and ignored.
+    pub fn parse(flags: &str) -> Result<Self, Box<dyn Error>> {
+        let mut parsed = Self::default();
+
+        for flag in flags.chars() {
+            match flag {
+                'a' => {}
+                'w' => parsed.writable = true,
+                'x' => parsed.executable = true,
+                'u' => parsed.uninitialized = true,
+                _ => return Err(format!("Invalid section flag: {flag}").into()),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// Returns the flags of a section that doesn't specify them.
+    pub fn default_for(name: &str) -> Self {
+        match name {
+            ".text" => Self {
+                executable: true,
+                ..Self::default()
+            },
+            ".data" => Self {
+                writable: true,
+                ..Self::default()
+            },
+            ".bss" => Self {
+                writable: true,
+                uninitialized: true,
+                ..Self::default()
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+/// A chunk of output with its own location counter, meant to be loaded at `base`.
+#[derive(Clone, Debug)]
+pub struct Section {
+    pub name: String,
+    pub flags: SectionFlags,
+    pub base: usize,
+    pub size: usize,
+    /// The contents of the section. Always empty for uninitialized sections.
+    pub bytes: Vec<u8>,
+    /// The offsets written by data directives and padding rather than
+    /// instructions, for the disassembler to leave alone.
+    pub data: Vec<Range<usize>>,
+}
+
+impl Section {
+    pub const DEFAULT: &'static str = ".text";
+
+    pub fn new(name: String, flags: SectionFlags, base: usize) -> Self {
+        Self {
+            name,
+            flags,
+            base,
+            size: 0,
+            bytes: vec![],
+            data: vec![],
+        }
+    }
+
+    pub fn extend(&mut self, bytes: impl IntoIterator<Item = u8>) -> Result<(), Box<dyn Error>> {
+        for byte in bytes {
+            if !self.flags.uninitialized {
+                self.bytes.push(byte);
+            } else if byte != 0 {
+                return Err(format!("Section {} can't hold initialized data", self.name).into());
+            }
+            self.size += 1;
+        }
+        Ok(())
+    }
+
+    /// Extends the section with bytes that aren't instructions, see [`Section::data`].
+    pub fn extend_data(
+        &mut self,
+        bytes: impl IntoIterator<Item = u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        let start = self.size;
+        self.extend(bytes)?;
+        match self.data.last_mut() {
+            Some(range) if range.end == start => range.end = self.size,
+            _ if start == self.size => {}
+            _ => self.data.push(start..self.size),
+        }
+        Ok(())
+    }
+
+    /// Writes the section as a memory file, as read by Verilog's `$readmemh`:
+    /// the index of its first word, then one word per line in hexadecimal.
+    /// Words are [`Architecture::word_size`] bytes long, in its endianness,
+    /// and the last one is padded with zeros.
+    pub fn memory_file<A: Architecture>(&self) -> String {
+        let word_size = A::word_size();
+        let mut file = format!("@{:x}\n", self.base * A::address_unit() / word_size);
+        for word in self.bytes.chunks(word_size) {
+            let mut word = word.to_vec();
+            word.resize(word_size, 0);
+            let _ = writeln!(
+                file,
+                "{:0digits$x}",
+                A::endianness().value(&word),
+                digits = word_size * 2
+            );
+        }
+        file
+    }
+}
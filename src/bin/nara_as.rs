@@ -0,0 +1,6 @@
+use nara_assembler_infrastructure::driver;
+
+/// See [`driver::run`].
+fn main() {
+    driver::run(std::env::args().skip(1), None);
+}
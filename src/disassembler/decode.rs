@@ -0,0 +1,160 @@
+use crate::arch_def::{Architecture, Instruction, OperandKind};
+use crate::assembler::AssemblerPass;
+use crate::assembler::passes::parse_operands::Operands;
+use std::fmt::{Debug, Formatter};
+use std::ops::Range;
+
+/// Decodes a stream of bytes into instructions.
+///
+/// Instruction words that don't start a known instruction are kept as raw
+/// bytes, and decoding resumes from the next word, see
+/// [`Architecture::instruction_word_size`]. Instructions may span several bytes,
+/// so the whole input is buffered and only decoded once finished.
+///
+/// Data and padding are also kept as raw bytes if their offsets are given,
+/// see [`DecodePass::with_data`].
+pub struct DecodePass<A: Architecture> {
+    bytes: Vec<u8>,
+    offset: usize,
+    data: Vec<Range<usize>>,
+    nodes: Vec<DecodedNode<A>>,
+}
+
+impl<A: Architecture> Default for DecodePass<A> {
+    fn default() -> Self {
+        Self {
+            bytes: vec![],
+            offset: 0,
+            data: vec![],
+            nodes: vec![],
+        }
+    }
+}
+
+impl<A: Architecture> AssemblerPass for DecodePass<A> {
+    type Input = u8;
+    type Output = DecodedNode<A>;
+
+    fn apply(&mut self, item: Self::Input) -> impl IntoIterator<Item = Self::Output> {
+        self.bytes.push(item);
+        vec![]
+    }
+
+    fn finish(&mut self) -> impl IntoIterator<Item = Self::Output> {
+        while self.offset < self.bytes.len() {
+            if let Some(range) = self.data.iter().find(|range| range.contains(&self.offset)) {
+                let end = range.end.min(self.bytes.len());
+                self.nodes.extend(
+                    self.bytes[self.offset..end]
+                        .iter()
+                        .copied()
+                        .map(DecodedNode::Byte),
+                );
+                self.offset = end;
+                continue;
+            }
+
+            // Instructions can't run into the data that follows them
+            let end = self
+                .data
+                .iter()
+                .map(|range| range.start)
+                .filter(|start| *start > self.offset)
+                .fold(self.bytes.len(), usize::min);
+            let bytes = &self.bytes[self.offset..end];
+            let decoded = A::Instruction::decode(bytes)
+                .filter(|(instruction, operands)| instruction.validate(operands).is_ok())
+                .and_then(|(instruction, operands)| {
+                    let size = instruction
+                        .emit(operands.iter().cloned())
+                        .into_iter()
+                        .count();
+                    (size > 0 && size <= bytes.len()).then_some((instruction, operands, size))
+                });
+
+            match decoded {
+                Some((instruction, operands, size)) => {
+                    self.nodes
+                        .push(DecodedNode::Instruction(instruction, operands));
+                    self.offset += size;
+                }
+                None => {
+                    let size = A::instruction_word_size().min(bytes.len());
+                    self.nodes
+                        .extend(bytes[..size].iter().copied().map(DecodedNode::Byte));
+                    self.offset += size;
+                }
+            }
+        }
+
+        std::mem::take(&mut self.nodes)
+    }
+}
+
+impl<A: Architecture> DecodePass<A> {
+    /// Keeps the bytes at these offsets as raw bytes, like [`Section::data`].
+    ///
+    /// [`Section::data`]: crate::assembler::sections::Section::data
+    pub fn with_data(mut self, data: Vec<Range<usize>>) -> Self {
+        self.data = data;
+        self
+    }
+}
+
+pub enum DecodedNode<A: Architecture> {
+    Instruction(A::Instruction, Operands<A>),
+    /// A byte that doesn't start a known instruction, or belongs to data.
+    Byte(u8),
+}
+
+impl<A: Architecture> Debug for DecodedNode<A>
+where
+    A::Instruction: Debug,
+    <A::OperandKind as OperandKind<A>>::Operand: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Instruction(instruction, operands) => {
+                write!(f, "Instruction({instruction:?}, {operands:?})")
+            }
+            Self::Byte(byte) => write!(f, "Byte({byte:#04x})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::architectures::test_arch::TestArch;
+    use crate::assembler::{AssemblerPass, AssemblerPasses, assemble};
+    use crate::disassembler::DisassemblerPasses;
+
+    #[test]
+    fn keeps_data_and_padding_as_bytes() {
+        let sections = assemble(
+            AssemblerPasses::<TestArch>::default(),
+            "halt\n.org 8\n.byte 1, 0, 0, 0, 0\nhalt\n",
+        );
+        let text = &sections[0];
+        let disassembly = Vec::from_iter(
+            DisassemblerPasses::<TestArch>::default()
+                .with_data(text.data.clone())
+                .apply_all(text.bytes.iter().copied()),
+        );
+
+        assert_eq!(
+            disassembly,
+            [
+                "halt",
+                ".byte 0x00",
+                ".byte 0x00",
+                ".byte 0x00",
+                ".byte 0x01",
+                ".byte 0x00",
+                ".byte 0x00",
+                ".byte 0x00",
+                ".byte 0x00",
+                "halt",
+            ]
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use crate::arch_def::Architecture;
+use crate::assembler::AssemblerPass;
+use crate::disassembler::decode::DecodePass;
+use crate::disassembler::print::PrintPass;
+use std::ops::Range;
+
+pub mod decode;
+pub mod print;
+
+/// Turns machine code back into assembly, one line per instruction.
+pub struct DisassemblerPasses<A: Architecture> {
+    decode: DecodePass<A>,
+    print: PrintPass<A>,
+}
+
+impl<A: Architecture> Default for DisassemblerPasses<A> {
+    fn default() -> Self {
+        Self {
+            decode: DecodePass::default(),
+            print: PrintPass::default(),
+        }
+    }
+}
+
+impl<A: Architecture> DisassemblerPasses<A> {
+    /// Prints the bytes at these offsets as data, see [`DecodePass::with_data`].
+    pub fn with_data(mut self, data: Vec<Range<usize>>) -> Self {
+        self.decode = self.decode.with_data(data);
+        self
+    }
+}
+
+impl<A: Architecture> AssemblerPass for DisassemblerPasses<A> {
+    type Input = <DecodePass<A> as AssemblerPass>::Input;
+    type Output = <PrintPass<A> as AssemblerPass>::Output;
+
+    fn apply(&mut self, item: Self::Input) -> impl IntoIterator<Item = Self::Output> {
+        let nodes = self.decode.apply(item);
+        self.print.apply_all_partial(nodes)
+    }
+
+    fn finish(&mut self) -> impl IntoIterator<Item = Self::Output> {
+        let nodes = self.decode.finish();
+        self.print.apply_all(nodes)
+    }
+}
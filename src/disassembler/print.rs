@@ -0,0 +1,45 @@
+use crate::arch_def::{Architecture, Instruction, OperandKind};
+use crate::assembler::AssemblerPass;
+use crate::disassembler::decode::DecodedNode;
+use std::marker::PhantomData;
+
+/// Prints each decoded node as a line of canonical assembly, which assembles
+/// back into the same bytes.
+pub struct PrintPass<A: Architecture> {
+    phantom_architecture: PhantomData<A>,
+}
+
+impl<A: Architecture> Default for PrintPass<A> {
+    fn default() -> Self {
+        Self {
+            phantom_architecture: PhantomData,
+        }
+    }
+}
+
+impl<A: Architecture> AssemblerPass for PrintPass<A> {
+    type Input = DecodedNode<A>;
+    type Output = String;
+
+    fn apply(&mut self, item: Self::Input) -> impl IntoIterator<Item = Self::Output> {
+        let line = match item {
+            DecodedNode::Instruction(instruction, operands) => {
+                let operands = instruction
+                    .operands()
+                    .into_iter()
+                    .zip(operands.iter())
+                    .map(|(kind, operand)| kind.format(operand))
+                    .collect::<Vec<_>>();
+
+                if operands.is_empty() {
+                    instruction.name().to_string()
+                } else {
+                    format!("{} {}", instruction.name(), operands.join(", "))
+                }
+            }
+            DecodedNode::Byte(byte) => format!(".byte {byte:#04x}"),
+        };
+
+        [line]
+    }
+}
@@ -0,0 +1,126 @@
+use crate::arch_def::CaseSensitivity;
+use crate::arch_def::dynamic::DynamicArchitecture;
+use crate::arch_def::registry::{AssemblerOptions, Registered, RegisteredArchitecture};
+use crate::architectures;
+use std::path::PathBuf;
+
+/// The command line of the assemblers: assembles for any of the registered
+/// architectures, see [`architectures::registry`], or for one described in a
+/// file, see [`DynamicArchitecture`].
+///
+/// `args` are the arguments after the name of the program. Without a file,
+/// `default_source` is assembled if given.
+///
+/// Usage: nara-as (--arch name | --arch-file isa.def) [--isa base+extension...]
+///                [--relax | --relax-scratch register]
+///                [--case-sensitive | --ignore-case]
+///                [-I include-dir]... [--disassemble | --memory-file] file
+///        nara-as (--arch name | --arch-file isa.def) --check
+///        nara-as --list-archs
+pub fn run(args: impl IntoIterator<Item = String>, default_source: Option<&str>) {
+    let registry = architectures::registry();
+    let mut arch = None;
+    let mut arch_file = None;
+    let mut options = AssemblerOptions::default();
+    let mut disassemble = false;
+    let mut memory_file = false;
+    let mut check = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--list-archs" => {
+                for architecture in registry.iter() {
+                    println!("{:<12} {}", architecture.name(), architecture.summary());
+                    if !architecture.extensions().is_empty() {
+                        println!(
+                            "{:<12} extensions: {}",
+                            "",
+                            architecture.extensions().join(", ")
+                        );
+                    }
+                }
+                return;
+            }
+            "--arch" => arch = Some(args.next().expect("Missing architecture after --arch")),
+            "--arch-file" => {
+                arch_file = Some(PathBuf::from(
+                    args.next()
+                        .expect("Missing description file after --arch-file"),
+                ))
+            }
+            "--isa" => options.isa = Some(args.next().expect("Missing ISA after --isa")),
+            "--relax" => options.relax = true,
+            "--relax-scratch" => {
+                options.relax_scratch =
+                    Some(args.next().expect("Missing register after --relax-scratch"))
+            }
+            "--disassemble" => disassemble = true,
+            "--memory-file" => memory_file = true,
+            "--check" => check = true,
+            "--case-sensitive" => options.case_sensitivity = Some(CaseSensitivity::Sensitive),
+            "--ignore-case" => options.case_sensitivity = Some(CaseSensitivity::Insensitive),
+            "-I" => options.include_paths.push(PathBuf::from(
+                args.next().expect("Missing include directory after -I"),
+            )),
+            _ if arg.starts_with("-I") => options.include_paths.push(PathBuf::from(&arg[2..])),
+            _ => options.file = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let described;
+    let architecture: &dyn RegisteredArchitecture = match (&arch, &arch_file) {
+        (Some(name), None) => registry.get(name).unwrap_or_else(|| {
+            let names = Vec::from_iter(registry.iter().map(|architecture| architecture.name()));
+            panic!(
+                "Unknown architecture {name}, expected one of: {}",
+                names.join(", ")
+            )
+        }),
+        (None, Some(arch_file)) => {
+            DynamicArchitecture::load(arch_file).unwrap_or_else(|error| panic!("{error}"));
+            described = Registered::<DynamicArchitecture>::new("file", "Described in a file");
+            &described
+        }
+        _ => panic!("Expected either --arch or --arch-file, see --list-archs"),
+    };
+    if check {
+        let report = architecture.validate();
+        println!("{report}");
+        if !report.is_ok() {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if disassemble {
+        let file = options.file.clone().expect("Missing file to disassemble");
+        let bytes = std::fs::read(&file)
+            .unwrap_or_else(|error| panic!("Can't read {}: {error}", file.display()));
+        for line in architecture.disassemble(&bytes) {
+            println!("{line}");
+        }
+        return;
+    }
+
+    let source = match (&options.file, default_source) {
+        (Some(file), _) => std::fs::read_to_string(file)
+            .unwrap_or_else(|error| panic!("Can't read {}: {error}", file.display())),
+        (None, Some(default_source)) => default_source.to_string(),
+        (None, None) => panic!("Missing file to assemble"),
+    };
+    let sections = architecture
+        .assemble(&source, &options)
+        .unwrap_or_else(|error| panic!("{error}"));
+
+    for section in sections {
+        if memory_file {
+            println!("// {}", section.name);
+            print!("{}", architecture.memory_file(&section));
+            continue;
+        }
+        println!(
+            "{} @ {:#06x} ({} bytes): {:02x?}",
+            section.name, section.base, section.size, section.bytes
+        );
+    }
+}
@@ -1,2 +1,5 @@
 pub mod arch_def;
+pub mod architectures;
 pub mod assembler;
+pub mod disassembler;
+pub mod driver;
@@ -1,173 +1,108 @@
-use itertools::Itertools;
-use nara_assembler_infrastructure::arch_def::{Architecture, Instruction, OperandKind, Symbol};
-use nara_assembler_infrastructure::assembler::passes::parse::PlausibleOperator;
+use nara_assembler_infrastructure::architectures::test_arch::TestArch;
 use nara_assembler_infrastructure::assembler::{AssemblerPass, AssemblerPasses};
-use std::error::Error;
-
-#[derive(Clone, Debug)]
-enum TestArch {}
-
-#[derive(Clone, Copy, Debug)]
-enum TestInstructions {
-    Xor,
-    Addi,
-    AddiImplicit,
-    Halt,
-    Jump,
-}
-
-const TEST_INSTRUCTIONS: &[TestInstructions] = &[
-    TestInstructions::Xor,
-    TestInstructions::Addi,
-    TestInstructions::AddiImplicit,
-    TestInstructions::Halt,
-    TestInstructions::Jump,
-];
-
-enum TestOperandKinds {
-    Register,
-    Immediate,
-}
-
-#[derive(Clone, Debug)]
-enum TestOperands {
-    Register(u8),
-    Immediate(i16),
-}
-
-#[derive(Clone, Debug)]
-enum TestSymbols {
-    Register(u8),
-}
-
-impl Architecture for TestArch {
-    type Instruction = TestInstructions;
-    type OperandKind = TestOperandKinds;
-    type Symbol = TestSymbols;
-}
-
-impl Instruction<TestArch> for TestInstructions {
-    fn name(&self) -> &str {
-        match self {
-            TestInstructions::Xor => "xor",
-            TestInstructions::Addi => "addi",
-            TestInstructions::AddiImplicit => "addi",
-            TestInstructions::Halt => "halt",
-            TestInstructions::Jump => "jump",
-        }
-    }
-
-    fn operands(&self) -> impl IntoIterator<Item = <TestArch as Architecture>::OperandKind> {
-        match self {
-            TestInstructions::Xor => vec![
-                TestOperandKinds::Register,
-                TestOperandKinds::Register,
-                TestOperandKinds::Register,
-            ],
-            TestInstructions::Addi => vec![
-                TestOperandKinds::Register,
-                TestOperandKinds::Register,
-                TestOperandKinds::Immediate,
-            ],
-            TestInstructions::AddiImplicit => {
-                vec![TestOperandKinds::Register, TestOperandKinds::Immediate]
-            }
-            TestInstructions::Halt => vec![],
-            TestInstructions::Jump => vec![TestOperandKinds::Immediate],
-        }
-    }
-
-    fn emit(
-        &self,
-        operands: impl IntoIterator<Item = TestOperands>,
-    ) -> impl IntoIterator<Item = u8> {
-        match self {
-            TestInstructions::Xor => {
-                let Some((
-                    TestOperands::Register(rd),
-                    TestOperands::Register(rs1),
-                    TestOperands::Register(rs2),
-                )) = operands.into_iter().collect_tuple()
-                else {
-                    unreachable!()
-                };
-                [0, rd, rs1, rs2, 0]
-            }
-            TestInstructions::Addi => {
-                let Some((
-                    TestOperands::Register(rd),
-                    TestOperands::Register(rs1),
-                    TestOperands::Immediate(imm),
-                )) = operands.into_iter().collect_tuple()
-                else {
-                    unreachable!()
-                };
-                [1, rd, rs1, imm as u8, (imm >> 8) as u8]
-            }
-            TestInstructions::AddiImplicit => {
-                let Some((TestOperands::Register(rd), TestOperands::Immediate(imm))) =
-                    operands.into_iter().collect_tuple()
-                else {
-                    unreachable!()
-                };
-                [1, rd, rd, imm as u8, (imm >> 8) as u8]
-            }
-            TestInstructions::Halt => [2, 0, 0, 0, 0],
-            TestInstructions::Jump => {
-                let Some(TestOperands::Immediate(imm)) = operands.into_iter().next() else {
-                    unreachable!()
-                };
-                [3, imm as u8, (imm >> 8) as u8, 0, 0]
-            }
-        }
-    }
-
-    fn enumerate() -> impl IntoIterator<Item = &'static Self> {
-        &TEST_INSTRUCTIONS[..]
-    }
-}
-
-impl OperandKind<TestArch> for TestOperandKinds {
-    type Operand = TestOperands;
-
-    fn parse(
-        &self,
-        plausible_operator: PlausibleOperator<TestArch>,
-    ) -> Result<Self::Operand, Box<dyn Error>> {
-        match (self, plausible_operator) {
-            (Self::Register, PlausibleOperator::Symbol(TestSymbols::Register(register))) => {
-                Ok(TestOperands::Register(register))
-            }
-            (Self::Immediate, PlausibleOperator::Value(value)) => {
-                Ok(TestOperands::Immediate(value.try_into()?))
-            }
-            _ => Err("The provided operand can't be accepted".into()),
-        }
-    }
-}
-
-impl Symbol<TestArch> for TestSymbols {
-    fn parse(symbol: &str) -> Result<Self, Box<dyn Error>> {
-        if symbol.starts_with('r') {
-            Ok(Self::Register(symbol[1..].parse()?))
-        } else {
-            Err(format!("Unparsable symbol: {}", symbol).into())
-        }
-    }
-}
+use nara_assembler_infrastructure::disassembler::DisassemblerPasses;
 
 fn main() {
-    let input = r"
+    let input = r#"
+        .macro clear reg, value=0
+            xor \reg, \reg, \reg
+            .if \value != 0
+            addi \reg, \value
+            .endif
+        .endm
+        .macro bytes values:vararg
+            .irp value, \values
+            .byte \value
+            .endr
+        .endm
+        .macro spin count, regs:vararg
+            again: addi \regs, -1
+            jump again
+        .endm
+
+        .equ BOARD, 2
+        .struct Pair; first: .byte; second: .word; .endstruct
+
+        start:
+        .if BOARD == 1
+            this is not even an instruction
+        .elseif BOARD == 2
+            addi r0, BOARD * 3 - 1
+        .else
+            halt
+        .endif
+        .ifndef DEBUG
+            halt
+        .endif
+        clear r1
+        clear r2, value=5
+        spin 1, r3
+        spin 2, r4
         xor r0, r0, r0
         addi r0, r0, 1
         addi r0, 1
-        halt; jump -1
-    ";
+        addi r1, r0, Pair.second
+        addi r1, Pair
+        li r5, 1000
+        li r6, end
+        .balign 8, 255
+        loop: halt; jump loop
+        1: jump 1f
+        1: jump 1b
+        .scope
+        loop: jump .next
+        .next: jump loop
+        .assert .next > loop, "scoped labels resolve in reports"
+        .endscope
+        .org 128
+        jump start
+        .section .rodata, "a", 64
+        .equ TABLE_SIZE, table_end - table
+        table: .byte 1, 2, 255
+        .word start, loop, -1
+        .rept 2
+        .irp value, 3, 4
+        .byte \value
+        .endr
+        .endr
+        .irpc digit, 567
+        .byte \digit * 2
+        .endr
+        bytes 8, 9
+        table_end:
+        .assert TABLE_SIZE <= 32, "table is ", TABLE_SIZE, " bytes long"
+        .text
+        jump table
+        end:
+    "#;
 
     let mut assembler_passes = AssemblerPasses::<TestArch>::default();
 
-    let bytes = assembler_passes.apply_all(input.chars());
-    let bytes = bytes.into_iter().collect_vec();
+    let sections = Vec::from_iter(assembler_passes.apply_all(input.chars()));
+
+    for section in &sections {
+        println!(
+            "{} @ {:#06x} ({} bytes): {:02x?}",
+            section.name, section.base, section.size, section.bytes
+        );
+    }
+
+    let text = sections
+        .iter()
+        .find(|section| section.name == ".text")
+        .unwrap();
+    let disassembly = Vec::from_iter(
+        DisassemblerPasses::<TestArch>::default()
+            .with_data(text.data.clone())
+            .apply_all(text.bytes.iter().copied()),
+    );
+    for line in &disassembly {
+        println!("    {line}");
+    }
 
-    println!("{:02x?}", bytes);
+    // The disassembly assembles back into the same bytes
+    let reassembled = Vec::from_iter(
+        AssemblerPasses::<TestArch>::default().apply_all(disassembly.join("\n").chars()),
+    );
+    assert_eq!(reassembled[0].bytes, text.bytes);
 }